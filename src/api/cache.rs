@@ -0,0 +1,218 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// The kinds of responses worth keeping around, each with its own time to live
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    Me,
+    Project,
+    Story,
+    Label,
+    Membership,
+}
+
+impl Resource {
+    /// Classifies a url path relative to the api base, e.g. `projects/1/stories/2`
+    pub fn from_path(path: &str) -> Option<Resource> {
+        let segments: Vec<&str> = path
+            .trim_matches('/')
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        match segments.as_slice() {
+            ["me"] => Some(Resource::Me),
+            ["projects", _] => Some(Resource::Project),
+            ["projects", _, "stories"]
+            | ["projects", _, "stories", _]
+            | ["projects", _, "stories", _, "tasks" | "comments"]
+            | ["stories", _] => Some(Resource::Story),
+            ["projects", _, "labels"] | ["projects", _, "labels", _] => Some(Resource::Label),
+            ["projects", _, "memberships"] => Some(Resource::Membership),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resource::Me => "me",
+            Resource::Project => "project",
+            Resource::Story => "story",
+            Resource::Label => "label",
+            Resource::Membership => "membership",
+        }
+    }
+}
+
+/// How long each kind of response is served from the cache before it's revalidated with tracker.
+///
+/// Stories default to zero so they're always revalidated (cheaply, with an etag), but are still
+/// around to be served when offline.
+#[derive(Debug, Clone)]
+pub struct CachePolicy {
+    pub me: Duration,
+    pub projects: Duration,
+    pub stories: Duration,
+    pub labels: Duration,
+    pub memberships: Duration,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy {
+            me: Duration::from_secs(60 * 60 * 24),
+            projects: Duration::from_secs(60 * 60),
+            stories: Duration::ZERO,
+            labels: Duration::from_secs(60 * 60),
+            memberships: Duration::from_secs(60 * 60 * 24),
+        }
+    }
+}
+
+impl CachePolicy {
+    pub fn ttl(&self, resource: Resource) -> Duration {
+        match resource {
+            Resource::Me => self.me,
+            Resource::Project => self.projects,
+            Resource::Story => self.stories,
+            Resource::Label => self.labels,
+            Resource::Membership => self.memberships,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub url: String,
+    pub resource: Resource,
+    pub etag: Option<String>,
+    /// unix timestamp, in seconds, of when tracker last confirmed the body
+    pub fetched_at: i64,
+    pub body: String,
+}
+
+impl CacheEntry {
+    pub fn age(&self) -> Duration {
+        let seconds = chrono::Utc::now().timestamp() - self.fetched_at;
+        Duration::from_secs(seconds.max(0) as u64)
+    }
+}
+
+/// Tracker responses stored on disk with cacache.
+///
+/// Failing to read or write the cache is never an error, it just means going to tracker.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    policy: CachePolicy,
+    offline: bool,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache {
+            dir: dir.into(),
+            policy: CachePolicy::default(),
+            offline: false,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: CachePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Serves whatever is cached regardless of age, and never goes to tracker
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    pub fn policy(&self) -> &CachePolicy {
+        &self.policy
+    }
+
+    pub async fn get(&self, key: &str) -> Option<CacheEntry> {
+        let bytes = cacache::read(&self.dir, key).await.ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    pub async fn put(&self, key: &str, entry: &CacheEntry) {
+        if let Ok(bytes) = serde_json::to_vec(entry) {
+            cacache::write(&self.dir, key, bytes).await.ok();
+        }
+    }
+
+    pub async fn remove(&self, key: &str) {
+        cacache::remove(&self.dir, key).await.ok();
+    }
+
+    pub async fn clear(&self) -> Result<(), cacache::Error> {
+        if !self.dir.exists() {
+            return Ok(());
+        }
+        cacache::clear(&self.dir).await
+    }
+
+    /// Everything in the cache that was put there by this client
+    pub async fn entries(&self) -> Vec<(cacache::Metadata, CacheEntry)> {
+        let mut entries = vec![];
+
+        for metadata in cacache::list_sync(&self.dir).flatten() {
+            if let Some(entry) = self.get(&metadata.key).await {
+                entries.push((metadata, entry));
+            }
+        }
+
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_from_path() {
+        assert_eq!(Resource::from_path("me"), Some(Resource::Me));
+        assert_eq!(Resource::from_path("/projects/1"), Some(Resource::Project));
+        assert_eq!(
+            Resource::from_path("projects/1/stories"),
+            Some(Resource::Story)
+        );
+        assert_eq!(
+            Resource::from_path("projects/1/stories/2"),
+            Some(Resource::Story)
+        );
+        assert_eq!(
+            Resource::from_path("projects/1/memberships"),
+            Some(Resource::Membership)
+        );
+        assert_eq!(
+            Resource::from_path("projects/1/labels"),
+            Some(Resource::Label)
+        );
+        assert_eq!(Resource::from_path("stories/2"), Some(Resource::Story));
+        assert_eq!(
+            Resource::from_path("projects/1/stories/2/tasks"),
+            Some(Resource::Story)
+        );
+        assert_eq!(
+            Resource::from_path("projects/1/stories/2/comments"),
+            Some(Resource::Story)
+        );
+        assert_eq!(Resource::from_path("projects/1/stories/2/tasks/3"), None);
+        assert_eq!(Resource::from_path("my/activity"), None);
+    }
+}
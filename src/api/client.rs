@@ -0,0 +1,700 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use reqwest::{header, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use sha256::digest;
+
+use super::cache::{Cache, CacheEntry, Resource};
+use super::schema::{
+    Activity, ApiError, Comment, Iteration, Label, Me, Project, ProjectMembership, Story,
+    StoryCreate, StoryDetail, StoryUpdate, Task,
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://www.pivotaltracker.com/services/v5";
+
+static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug)]
+pub enum TrackerError {
+    /// tracker responded with an error document, e.g. a 404 or a validation problem
+    Api {
+        status: StatusCode,
+        error: ApiError,
+    },
+    /// tracker responded with an error that isn't shaped like an `ApiError`
+    Unexpected {
+        status: StatusCode,
+        body: String,
+    },
+    /// the request needs tracker, but the client is offline and nothing is cached
+    Offline {
+        url: String,
+    },
+    /// the api token can't be sent as a header
+    InvalidToken(header::InvalidHeaderValue),
+    Http(reqwest::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for TrackerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrackerError::Api { status, error } => {
+                let problem = error.general_problem.as_ref().unwrap_or(&error.error);
+                write!(
+                    f,
+                    "tracker api error: {} ({})\n\n{}",
+                    error.code, status, problem
+                )?;
+
+                if let Some(validation_errors) = &error.validation_errors {
+                    writeln!(f)?;
+                    for validation_error in validation_errors {
+                        write!(
+                            f,
+                            "\n  {}: {}",
+                            validation_error.field, validation_error.problem
+                        )?;
+                    }
+                }
+
+                Ok(())
+            }
+            TrackerError::Unexpected { status, body } => {
+                write!(f, "tracker api error: {}\n\n{}", status, body)
+            }
+            TrackerError::Offline { url } => {
+                write!(
+                    f,
+                    "{} isn't cached, and can't be fetched while offline",
+                    url
+                )
+            }
+            TrackerError::InvalidToken(_) => write!(f, "the tracker api token isn't valid"),
+            TrackerError::Http(err) => write!(f, "failed to talk to tracker: {}", err),
+            TrackerError::Json(err) => write!(f, "failed to parse tracker's response: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TrackerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TrackerError::InvalidToken(err) => Some(err),
+            TrackerError::Http(err) => Some(err),
+            TrackerError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for TrackerError {
+    fn from(err: reqwest::Error) -> Self {
+        TrackerError::Http(err)
+    }
+}
+
+impl From<serde_json::Error> for TrackerError {
+    fn from(err: serde_json::Error) -> Self {
+        TrackerError::Json(err)
+    }
+}
+
+impl From<header::InvalidHeaderValue> for TrackerError {
+    fn from(err: header::InvalidHeaderValue) -> Self {
+        TrackerError::InvalidToken(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, TrackerError>;
+
+/// A paginated list from tracker, fetched a page at a time as it's read
+pub struct Pages<'a, T> {
+    client: &'a TrackerClient,
+    url: String,
+    query: Vec<(String, String)>,
+    page_size: u32,
+    offset: u32,
+    buffer: VecDeque<T>,
+    done: bool,
+}
+
+impl<T: DeserializeOwned> Pages<'_, T> {
+    /// The next item, fetching another page once the last one has been read
+    pub async fn next(&mut self) -> Result<Option<T>> {
+        while self.buffer.is_empty() && !self.done {
+            self.fetch_next_page().await?;
+        }
+        Ok(self.buffer.pop_front())
+    }
+
+    /// Up to `limit` items, or every one of them
+    pub async fn collect(mut self, limit: Option<usize>) -> Result<Vec<T>> {
+        if let Some(limit) = limit {
+            self.page_size = self.page_size.min(limit.max(1) as u32);
+        }
+
+        self.collect_where(limit, |_| true).await
+    }
+
+    /// Up to `limit` of the items `keep` picks out, or every one of them. Pages stay full
+    /// sized, since there's no telling how many items will be passed over.
+    pub async fn collect_where(
+        mut self,
+        limit: Option<usize>,
+        keep: impl Fn(&T) -> bool,
+    ) -> Result<Vec<T>> {
+        let mut items = vec![];
+        while limit.is_none_or(|limit| items.len() < limit) {
+            match self.next().await? {
+                Some(item) if keep(&item) => items.push(item),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Ok(items)
+    }
+
+    async fn fetch_next_page(&mut self) -> Result<()> {
+        let request = self
+            .client
+            .http
+            .get(&self.url)
+            .query(&self.query)
+            .query(&[("limit", self.page_size), ("offset", self.offset)]);
+        let (page, pagination) = self.client.fetch_page::<T>(request).await?;
+
+        match pagination.and_then(|p| p.next_offset()) {
+            Some(next) => self.offset = next,
+            None => self.done = true,
+        }
+        self.buffer.extend(page);
+        Ok(())
+    }
+}
+
+/// How many items are asked for at a time when paging through a list
+const PAGE_SIZE: u32 = 100;
+
+/// Where a page sits in a paginated list, from tracker's X-Tracker-Pagination-* headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    pub total: u32,
+    pub limit: u32,
+    pub offset: u32,
+    pub returned: u32,
+}
+
+impl Pagination {
+    pub fn from_headers(headers: &header::HeaderMap) -> Option<Pagination> {
+        let get = |name: &str| -> Option<u32> {
+            headers
+                .get(format!("X-Tracker-Pagination-{}", name))?
+                .to_str()
+                .ok()?
+                .parse()
+                .ok()
+        };
+
+        Some(Pagination {
+            total: get("Total")?,
+            limit: get("Limit")?,
+            offset: get("Offset")?,
+            returned: get("Returned")?,
+        })
+    }
+
+    /// The offset of the page after this one, if there is one
+    pub fn next_offset(&self) -> Option<u32> {
+        let next = self.offset + self.returned;
+        (self.returned > 0 && next < self.total).then_some(next)
+    }
+}
+
+/// Authenticated access to the tracker api.
+///
+/// https://www.pivotaltracker.com/help/api/rest/v5
+#[derive(Clone, Debug)]
+pub struct TrackerClient {
+    http: reqwest::Client,
+    base_url: String,
+    /// keeps cache entries from one token from being served to another
+    token_digest: String,
+    cache: Option<Cache>,
+}
+
+impl TrackerClient {
+    pub fn new(token: &str) -> Result<Self> {
+        Self::with_base_url(token, DEFAULT_BASE_URL)
+    }
+
+    pub fn with_base_url(token: &str, base_url: impl Into<String>) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        if !token.is_empty() {
+            let mut api_token_value = header::HeaderValue::from_str(token)?;
+            api_token_value.set_sensitive(true);
+
+            headers.insert("X-TrackerToken", api_token_value);
+        }
+
+        let http = reqwest::Client::builder()
+            .user_agent(APP_USER_AGENT)
+            .default_headers(headers)
+            .build()?;
+
+        Ok(TrackerClient {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token_digest: digest(token),
+            cache: None,
+        })
+    }
+
+    /// Keeps responses for stories, projects, labels and memberships in `cache`
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    /// Fetches `path` (relative to the base url) and returns the response body untouched
+    pub async fn get_raw(&self, path: &str) -> Result<String> {
+        self.fetch(self.http.get(self.url(path))).await
+    }
+
+    /// Drops the cached response for `path`, e.g. `me` once the token has been logged out
+    pub async fn forget(&self, path: &str) {
+        if let Some(cache) = &self.cache {
+            cache.remove(&self.cache_key(&self.url(path))).await;
+        }
+    }
+
+    pub async fn me(&self) -> Result<Me> {
+        self.send(self.http.get(self.url("me"))).await
+    }
+
+    pub async fn get_project(&self, project_id: u64) -> Result<Project> {
+        let url = self.url(&format!("projects/{}", project_id));
+        self.send(self.http.get(url)).await
+    }
+
+    /// The iteration in progress, with its stories, if the project has one
+    pub async fn current_iteration(&self, project_id: u64) -> Result<Option<Iteration>> {
+        Ok(self
+            .list_iterations(project_id, "current")
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Iterations with their stories, `scope` is one of current, backlog, current_backlog or done
+    pub async fn list_iterations(&self, project_id: u64, scope: &str) -> Result<Vec<Iteration>> {
+        let url = self.url(&format!("projects/{}/iterations", project_id));
+        self.send(self.http.get(url).query(&[("scope", scope)]))
+            .await
+    }
+
+    pub async fn get_iteration(&self, project_id: u64, number: u32) -> Result<Iteration> {
+        let url = self.url(&format!("projects/{}/iterations/{}", project_id, number));
+        self.send(self.http.get(url)).await
+    }
+
+    pub async fn list_labels(&self, project_id: u64) -> Result<Vec<Label>> {
+        let url = self.url(&format!("projects/{}/labels", project_id));
+        self.send(self.http.get(url)).await
+    }
+
+    pub async fn project_memberships(&self, project_id: u64) -> Result<Vec<ProjectMembership>> {
+        let url = self.url(&format!("projects/{}/memberships", project_id));
+        self.send(self.http.get(url)).await
+    }
+
+    pub async fn get_story(&self, project_id: u64, story_id: u64) -> Result<StoryDetail> {
+        let url = self.url(&format!("projects/{}/stories/{}", project_id, story_id));
+        self.send(self.http.get(url)).await
+    }
+
+    /// Fetches a story without knowing its project, e.g. to find out which project it's in
+    pub async fn find_story(&self, story_id: u64) -> Result<StoryDetail> {
+        let url = self.url(&format!("stories/{}", story_id));
+        self.send(self.http.get(url)).await
+    }
+
+    pub async fn create_story(&self, project_id: u64, story: &StoryCreate) -> Result<StoryDetail> {
+        let url = self.url(&format!("projects/{}/stories", project_id));
+        self.send(self.http.post(url).json(story)).await
+    }
+
+    pub async fn update_story(
+        &self,
+        project_id: u64,
+        story_id: u64,
+        changes: &StoryUpdate,
+    ) -> Result<StoryDetail> {
+        let url = self.url(&format!("projects/{}/stories/{}", project_id, story_id));
+        self.send(self.http.put(url).json(changes)).await
+    }
+
+    /// A story's tasks, in order
+    pub async fn list_tasks(&self, project_id: u64, story_id: u64) -> Result<Vec<Task>> {
+        let url = self.url(&format!(
+            "projects/{}/stories/{}/tasks",
+            project_id, story_id
+        ));
+        self.send(self.http.get(url)).await
+    }
+
+    pub async fn create_task(
+        &self,
+        project_id: u64,
+        story_id: u64,
+        description: &str,
+    ) -> Result<Task> {
+        let url = self.url(&format!(
+            "projects/{}/stories/{}/tasks",
+            project_id, story_id
+        ));
+        let body = serde_json::json!({ "description": description });
+        self.send(self.http.post(url).json(&body)).await
+    }
+
+    pub async fn complete_task(
+        &self,
+        project_id: u64,
+        story_id: u64,
+        task_id: u64,
+        complete: bool,
+    ) -> Result<Task> {
+        let url = self.url(&format!(
+            "projects/{}/stories/{}/tasks/{}",
+            project_id, story_id, task_id
+        ));
+        let body = serde_json::json!({ "complete": complete });
+        self.send(self.http.put(url).json(&body)).await
+    }
+
+    pub async fn delete_task(&self, project_id: u64, story_id: u64, task_id: u64) -> Result<()> {
+        let url = self.url(&format!(
+            "projects/{}/stories/{}/tasks/{}",
+            project_id, story_id, task_id
+        ));
+        self.send_empty(self.http.delete(url)).await
+    }
+
+    /// A story's comments, oldest first, with their authors
+    pub async fn list_comments(&self, project_id: u64, story_id: u64) -> Result<Vec<Comment>> {
+        let url = self.url(&format!(
+            "projects/{}/stories/{}/comments",
+            project_id, story_id
+        ));
+        let request = self.http.get(url).query(&[("fields", ":default,person")]);
+        self.send(request).await
+    }
+
+    pub async fn get_comment(
+        &self,
+        project_id: u64,
+        story_id: u64,
+        comment_id: u64,
+    ) -> Result<Comment> {
+        let url = self.url(&format!(
+            "projects/{}/stories/{}/comments/{}",
+            project_id, story_id, comment_id
+        ));
+        let request = self.http.get(url).query(&[("fields", ":default,person")]);
+        self.send(request).await
+    }
+
+    pub async fn create_comment(
+        &self,
+        project_id: u64,
+        story_id: u64,
+        text: &str,
+    ) -> Result<Comment> {
+        let url = self.url(&format!(
+            "projects/{}/stories/{}/comments",
+            project_id, story_id
+        ));
+        let body = serde_json::json!({ "text": text });
+        self.send(self.http.post(url).json(&body)).await
+    }
+
+    /// Lists a project's stories, optionally narrowed with tracker's search syntax, e.g.
+    /// `mywork:123`
+    ///
+    /// https://www.pivotaltracker.com/help/api/rest/v5#Stories
+    pub async fn list_stories(&self, project_id: u64, filter: Option<&str>) -> Result<Vec<Story>> {
+        let url = self.url(&format!("projects/{}/stories", project_id));
+        let mut request = self.http.get(url);
+        if let Some(filter) = filter {
+            request = request.query(&[("filter", filter)]);
+        }
+        self.send(request).await
+    }
+
+    /// Stories matching a tracker search filter, e.g. `label:"needs review" state:started`,
+    /// a page at a time
+    pub fn search_stories(&self, project_id: u64, filter: &str) -> Pages<'_, Story> {
+        self.paginate(
+            &format!("projects/{}/stories", project_id),
+            &[("filter", filter)],
+        )
+    }
+
+    /// Everything you've done across projects, newest first, optionally only since
+    /// `occurred_after` (an rfc 3339 timestamp)
+    pub fn my_activity(&self, occurred_after: Option<&str>) -> Pages<'_, Activity> {
+        match occurred_after {
+            Some(timestamp) => self.paginate("my/activity", &[("occurred_after", timestamp)]),
+            None => self.paginate("my/activity", &[]),
+        }
+    }
+
+    /// The list at `path` (relative to the base url), fetched a page at a time as it's read by
+    /// following the pagination headers. Pages aren't cached.
+    pub fn paginate<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Pages<'_, T> {
+        Pages {
+            client: self,
+            url: self.url(path),
+            query: query
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            page_size: PAGE_SIZE,
+            offset: 0,
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let body = self.fetch(request).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Like `send`, for requests that don't respond with anything, e.g. deletes
+    async fn send_empty(&self, request: RequestBuilder) -> Result<()> {
+        self.fetch(request).await?;
+        Ok(())
+    }
+
+    /// Sends the request and returns the response body, going through the cache when there is
+    /// one: fresh entries are served as is, stale ones are revalidated with their etag, and
+    /// changes to a resource drop its cached copy.
+    async fn fetch(&self, request: RequestBuilder) -> Result<String> {
+        let mut request = request.build()?;
+        let url = request.url().to_string();
+
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => return self.execute(request).await,
+        };
+
+        let key = self.cache_key(&url);
+
+        if request.method() != Method::GET {
+            if cache.is_offline() {
+                return Err(TrackerError::Offline { url });
+            }
+            let body = self.execute(request).await?;
+            let resource_url = url.split('?').next().unwrap_or(&url);
+            cache.remove(&self.cache_key(resource_url)).await;
+            return Ok(body);
+        }
+
+        let resource = match self.resource(&url) {
+            Some(resource) => resource,
+            None if cache.is_offline() => return Err(TrackerError::Offline { url }),
+            None => return self.execute(request).await,
+        };
+
+        let cached = cache.get(&key).await;
+
+        match &cached {
+            Some(entry) if cache.is_offline() || entry.age() < cache.policy().ttl(resource) => {
+                return Ok(entry.body.clone());
+            }
+            Some(CacheEntry {
+                etag: Some(etag), ..
+            }) => {
+                if let Ok(value) = header::HeaderValue::from_str(etag) {
+                    request.headers_mut().insert(header::IF_NONE_MATCH, value);
+                }
+            }
+            Some(_) => {}
+            None if cache.is_offline() => return Err(TrackerError::Offline { url }),
+            None => {}
+        }
+
+        let response = self.http.execute(request).await?;
+        let status = response.status();
+
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(mut entry) = cached {
+                entry.fetched_at = chrono::Utc::now().timestamp();
+                cache.put(&key, &entry).await;
+                return Ok(entry.body);
+            }
+        }
+
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response.text().await?;
+
+        if !status.is_success() {
+            return Err(api_error(status, body));
+        }
+
+        let entry = CacheEntry {
+            url,
+            resource,
+            etag,
+            fetched_at: chrono::Utc::now().timestamp(),
+            body,
+        };
+        cache.put(&key, &entry).await;
+
+        Ok(entry.body)
+    }
+
+    async fn fetch_page<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<(Vec<T>, Option<Pagination>)> {
+        let request = request.build()?;
+
+        if self.cache.as_ref().is_some_and(Cache::is_offline) {
+            return Err(TrackerError::Offline {
+                url: request.url().to_string(),
+            });
+        }
+
+        let response = self.http.execute(request).await?;
+        let status = response.status();
+        let pagination = Pagination::from_headers(response.headers());
+        let body = response.text().await?;
+
+        if !status.is_success() {
+            return Err(api_error(status, body));
+        }
+
+        Ok((serde_json::from_str(&body)?, pagination))
+    }
+
+    async fn execute(&self, request: reqwest::Request) -> Result<String> {
+        let response = self.http.execute(request).await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        if !status.is_success() {
+            return Err(api_error(status, body));
+        }
+
+        Ok(body)
+    }
+
+    fn cache_key(&self, url: &str) -> String {
+        format!("tracker::{}::{}", &self.token_digest[..16], url)
+    }
+
+    /// What kind of resource a url refers to, if it's one that's cached
+    fn resource(&self, url: &str) -> Option<Resource> {
+        let path = url.strip_prefix(&self.base_url)?;
+        Resource::from_path(path.split('?').next().unwrap_or_default())
+    }
+}
+
+fn api_error(status: StatusCode, body: String) -> TrackerError {
+    match serde_json::from_str::<ApiError>(&body) {
+        Ok(error) => TrackerError::Api { status, error },
+        Err(_) => TrackerError::Unexpected { status, body },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagination() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(Pagination::from_headers(&headers), None);
+
+        for (name, value) in [
+            ("Total", "250"),
+            ("Limit", "100"),
+            ("Offset", "100"),
+            ("Returned", "100"),
+        ] {
+            headers.insert(
+                header::HeaderName::from_bytes(format!("X-Tracker-Pagination-{}", name).as_bytes())
+                    .unwrap(),
+                header::HeaderValue::from_static(value),
+            );
+        }
+
+        let pagination = Pagination::from_headers(&headers).unwrap();
+        assert_eq!(pagination.next_offset(), Some(200));
+
+        let last = Pagination {
+            offset: 200,
+            returned: 50,
+            ..pagination
+        };
+        assert_eq!(last.next_offset(), None);
+
+        let empty = Pagination {
+            offset: 200,
+            returned: 0,
+            ..pagination
+        };
+        assert_eq!(empty.next_offset(), None);
+    }
+
+    #[test]
+    fn test_url() {
+        let client = TrackerClient::with_base_url("token", "http://localhost:1234/v5/").unwrap();
+        assert_eq!(client.url("/me"), "http://localhost:1234/v5/me");
+        assert_eq!(
+            client.url("projects/1/stories/2"),
+            "http://localhost:1234/v5/projects/1/stories/2"
+        );
+    }
+
+    #[test]
+    fn test_api_error() {
+        let body = r#"{"code":"invalid_parameter","kind":"error","error":"One or more request parameters was missing or invalid.","general_problem":"Stories in the started state must be estimated.","validation_errors":[{"field":"estimate","problem":"Stories in the started state must be estimated."}]}"#;
+
+        match api_error(StatusCode::BAD_REQUEST, body.to_string()) {
+            TrackerError::Api { status, error } => {
+                assert_eq!(status, StatusCode::BAD_REQUEST);
+                assert_eq!(error.validation_errors.unwrap()[0].field, "estimate");
+            }
+            other => panic!("expected an api error, got {:?}", other),
+        }
+
+        assert!(matches!(
+            api_error(StatusCode::BAD_GATEWAY, "<html>".to_string()),
+            TrackerError::Unexpected { .. }
+        ));
+    }
+}
@@ -1 +1,6 @@
+pub mod cache;
+pub mod client;
 pub mod schema;
+
+pub use cache::Cache;
+pub use client::{TrackerClient, TrackerError};
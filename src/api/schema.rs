@@ -1,18 +1,24 @@
+use std::fmt;
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Story {
     pub name: String,
     pub id: u32,
+    pub project_id: u64,
     pub current_state: StoryState,
     pub story_type: StoryType,
     pub url: String,
     #[serde(default)]
     pub estimate: Option<u32>,
     pub labels: Vec<Label>,
+    #[serde(default)]
+    pub owner_ids: Vec<u64>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StoryType {
     #[serde(rename = "bug")]
     Bug,
@@ -24,7 +30,36 @@ pub enum StoryType {
     Release,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl fmt::Display for StoryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StoryType::Bug => "bug",
+            StoryType::Feature => "feature",
+            StoryType::Chore => "chore",
+            StoryType::Release => "release",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for StoryType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "bug" => Ok(StoryType::Bug),
+            "feature" => Ok(StoryType::Feature),
+            "chore" => Ok(StoryType::Chore),
+            "release" => Ok(StoryType::Release),
+            other => Err(format!(
+                "unknown story type {:?}, expected feature, bug, chore or release",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StoryState {
     #[serde(rename = "accepted")]
     Accepted,
@@ -44,19 +79,152 @@ pub enum StoryState {
     Unscheduled,
 }
 
+impl StoryState {
+    /// Whether tracker's workflow allows moving a story from this state to `next`.
+    ///
+    /// Features and bugs go started → finished → delivered → accepted or rejected, while chores
+    /// and releases are accepted straight from started. Rejected stories get restarted.
+    pub fn can_transition_to(&self, story_type: &StoryType, next: &StoryState) -> bool {
+        use StoryState::*;
+
+        let needs_delivery = matches!(story_type, StoryType::Feature | StoryType::Bug);
+
+        match (self, next) {
+            (Unscheduled | Unstarted | Planned | Rejected, Started) => true,
+            (Started, Finished) => needs_delivery,
+            (Started, Accepted) => !needs_delivery,
+            (Finished, Delivered) => needs_delivery,
+            (Delivered, Accepted | Rejected) => needs_delivery,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for StoryState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "accepted" => Ok(StoryState::Accepted),
+            "delivered" => Ok(StoryState::Delivered),
+            "finished" => Ok(StoryState::Finished),
+            "started" => Ok(StoryState::Started),
+            "rejected" => Ok(StoryState::Rejected),
+            "planned" => Ok(StoryState::Planned),
+            "unstarted" => Ok(StoryState::Unstarted),
+            "unscheduled" => Ok(StoryState::Unscheduled),
+            other => Err(format!(
+                "unknown story state {:?}, expected one of unscheduled, unstarted, planned, started, finished, delivered, rejected or accepted",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for StoryState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StoryState::Accepted => "accepted",
+            StoryState::Delivered => "delivered",
+            StoryState::Finished => "finished",
+            StoryState::Started => "started",
+            StoryState::Rejected => "rejected",
+            StoryState::Planned => "planned",
+            StoryState::Unstarted => "unstarted",
+            StoryState::Unscheduled => "unscheduled",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct StoryDetail {
     pub name: String,
     pub id: u32,
+    pub project_id: u64,
     pub current_state: StoryState,
     pub story_type: StoryType,
     pub url: String,
     #[serde(default)]
     pub estimate: Option<u32>,
     pub labels: Vec<Label>,
+    #[serde(default)]
+    pub owner_ids: Vec<u64>,
     pub description: Option<String>,
 }
 
+/// The fields of a story that can be changed with a PUT, unset fields are left alone
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct StoryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub story_type: Option<StoryType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_state: Option<StoryState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_ids: Option<Vec<u64>>,
+    /// `Some(None)` removes the estimate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<Option<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<LabelName>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl StoryUpdate {
+    pub fn is_empty(&self) -> bool {
+        self == &StoryUpdate::default()
+    }
+}
+
+/// A story to be created with a POST
+#[derive(Serialize, Debug)]
+pub struct StoryCreate {
+    pub name: String,
+    pub story_type: StoryType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<LabelName>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub owner_ids: Vec<u64>,
+}
+
+/// Refers to a label by name, tracker creates it if it doesn't exist yet
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LabelName {
+    pub name: String,
+}
+
+// https://www.pivotaltracker.com/help/api/rest/v5#task_resource
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Task {
+    pub id: u64,
+    pub story_id: u64,
+    pub description: String,
+    pub complete: bool,
+    pub position: u32,
+}
+
+// https://www.pivotaltracker.com/help/api/rest/v5#comment_resource
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Comment {
+    pub id: u64,
+    pub story_id: Option<u64>,
+    pub text: Option<String>,
+    pub person_id: u64,
+    /// Only present when requested with `fields=:default,person`
+    #[serde(default)]
+    pub person: Option<Person>,
+    pub created_at: String,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Label {
     pub id: u64,
@@ -67,6 +235,32 @@ pub struct Label {
     pub updated_at: String,
 }
 
+// https://www.pivotaltracker.com/help/api/rest/v5#project_resource
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Project {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub current_velocity: Option<u32>,
+    #[serde(default)]
+    pub current_iteration_number: Option<u32>,
+    /// in weeks
+    #[serde(default)]
+    pub iteration_length: Option<u32>,
+}
+
+// https://www.pivotaltracker.com/help/api/rest/v5#iteration_resource
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Iteration {
+    pub number: u32,
+    pub start: String,
+    pub finish: String,
+    #[serde(default)]
+    pub stories: Vec<Story>,
+}
+
 // https://www.pivotaltracker.com/help/api/rest/v5#activity_resource
 
 #[derive(Deserialize, Debug)]
@@ -76,6 +270,7 @@ pub struct Activity {
     pub highlight: String,
     pub primary_resources: Vec<ActivityEntityReference>,
     pub project: EntityReference,
+    pub performed_by: Option<PersonReference>,
     pub occurred_at: String,
 }
 
@@ -86,11 +281,36 @@ pub struct EntityReference {
     pub name: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct PersonReference {
+    pub id: u64,
+    pub name: String,
+    pub initials: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ActivityEntityReference {
     pub kind: String,
     pub id: Option<u64>,
     pub name: Option<String>,
+    pub url: Option<String>,
+}
+
+// https://www.pivotaltracker.com/help/api/rest/v5#project_membership_resource
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProjectMembership {
+    pub id: u64,
+    pub person: Person,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Person {
+    pub id: u64,
+    pub name: String,
+    pub initials: String,
+    pub username: String,
+    pub email: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -131,11 +351,14 @@ pub struct ApiError {
     pub validation_errors: Option<Vec<ValidationError>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum MaybeStoryDetail {
-    StoryDetail(StoryDetail),
-    ApiError(ApiError),
+impl ApiError {
+    /// The validation problem reported for `field`, if any
+    pub fn validation_error(&self, field: &str) -> Option<&ValidationError> {
+        self.validation_errors
+            .as_ref()?
+            .iter()
+            .find(|error| error.field == field)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -146,3 +369,29 @@ pub struct ValidationError {
     #[serde(rename = "problem")]
     pub problem: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_can_transition_to() {
+        use StoryState::*;
+
+        let feature = StoryType::Feature;
+        assert!(Unstarted.can_transition_to(&feature, &Started));
+        assert!(Started.can_transition_to(&feature, &Finished));
+        assert!(Finished.can_transition_to(&feature, &Delivered));
+        assert!(Delivered.can_transition_to(&feature, &Accepted));
+        assert!(Delivered.can_transition_to(&feature, &Rejected));
+        assert!(Rejected.can_transition_to(&feature, &Started));
+        assert!(!Started.can_transition_to(&feature, &Accepted));
+        assert!(!Unstarted.can_transition_to(&feature, &Finished));
+        assert!(!Accepted.can_transition_to(&feature, &Started));
+
+        let chore = StoryType::Chore;
+        assert!(Started.can_transition_to(&chore, &Accepted));
+        assert!(!Started.can_transition_to(&chore, &Finished));
+        assert!(!Delivered.can_transition_to(&chore, &Rejected));
+    }
+}
@@ -0,0 +1,200 @@
+use anyhow::anyhow;
+use regex::Regex;
+use slugify::slugify;
+
+use crate::template;
+
+pub const DEFAULT_TEMPLATE: &str = "{slug}-{id}";
+
+/// The placeholders branch templates can use
+pub const PLACEHOLDERS: [&str; 5] = ["type", "conventional_type", "slug", "id", "initials"];
+
+/// What goes in a branch name
+pub struct Parts<'a> {
+    pub story_type: &'a str,
+    pub conventional_type: &'a str,
+    pub slug: String,
+    pub id: u64,
+    pub initials: &'a str,
+}
+
+/// Fills in `template`, making sure the story id can be read back out of the result
+pub fn render(template: &str, parts: &Parts) -> anyhow::Result<String> {
+    if !template.contains("{id}") {
+        return Err(anyhow!(
+            "the branch template {:?} needs an {{id}} so stories can find the story again",
+            template
+        ));
+    }
+
+    let id = parts.id.to_string();
+    let initials = parts.initials.to_lowercase();
+    let name = template::render(
+        template,
+        &[
+            ("type", parts.story_type),
+            ("conventional_type", parts.conventional_type),
+            ("slug", &parts.slug),
+            ("id", &id),
+            ("initials", &initials),
+        ],
+    );
+
+    match extract_id(template, &name) {
+        Some(extracted) if extracted == parts.id => Ok(name),
+        _ => Err(anyhow!(
+            "couldn't read the story id back out of {:?}, made with the branch template {:?}",
+            name,
+            template
+        )),
+    }
+}
+
+/// Lowercases `text` and joins its words with dashes, cut to `max_length` at most. Accents
+/// and other scripts are spelled out in ascii when `transliterate`, and kept as they are
+/// otherwise.
+pub fn slug(text: &str, max_length: usize, transliterate: bool) -> String {
+    if transliterate {
+        return slugify!(text, max_length = max_length);
+    }
+
+    let words = text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    words
+        .chars()
+        .take(max_length)
+        .collect::<String>()
+        .trim_end_matches('-')
+        .to_string()
+}
+
+/// Reads the story id out of a branch made with `template`. Branches that don't fit the
+/// template, like ones made before it was set, fall back to their last number.
+pub fn extract_id(template: &str, branch: &str) -> Option<u64> {
+    template_pattern(template)
+        .and_then(|pattern| pattern.captures(branch))
+        .and_then(|captures| captures.name("id")?.as_str().parse().ok())
+        .or_else(|| last_number(branch))
+}
+
+fn last_number(s: &str) -> Option<u64> {
+    s.split(|c: char| !c.is_numeric())
+        .filter_map(|s| s.parse::<u64>().ok())
+        .next_back()
+}
+
+fn template_pattern(template: &str) -> Option<Regex> {
+    let placeholders = Regex::new(r"\{([a-z_]+)\}").ok()?;
+
+    let mut pattern = String::from("^");
+    let mut last = 0;
+    let mut has_id = false;
+    for captures in placeholders.captures_iter(template) {
+        let placeholder = captures.get(0)?;
+        let name = captures.get(1)?.as_str();
+        if !PLACEHOLDERS.contains(&name) {
+            continue;
+        }
+
+        pattern.push_str(&regex::escape(&template[last..placeholder.start()]));
+        if name == "id" && !has_id {
+            pattern.push_str(r"(?P<id>\d+)");
+            has_id = true;
+        } else {
+            pattern.push_str(".*?");
+        }
+        last = placeholder.end();
+    }
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+
+    Regex::new(&pattern).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(name: &str) -> Parts<'static> {
+        Parts {
+            story_type: "feature",
+            conventional_type: "feat",
+            slug: slug(name, 40, true),
+            id: 555,
+            initials: "DD",
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render(DEFAULT_TEMPLATE, &parts("Add a widget")).unwrap(),
+            "add-a-widget-555"
+        );
+        assert_eq!(
+            render("{conventional_type}/PT-{id}-{slug}", &parts("Add a widget")).unwrap(),
+            "feat/PT-555-add-a-widget"
+        );
+        assert_eq!(
+            render("{initials}/{type}/{slug}-{id}", &parts("Add a widget")).unwrap(),
+            "dd/feature/add-a-widget-555"
+        );
+        assert!(render("{slug}", &parts("Add a widget")).is_err());
+    }
+
+    #[test]
+    fn test_extract_id() {
+        assert_eq!(extract_id(DEFAULT_TEMPLATE, "yep-123"), Some(123));
+        assert_eq!(extract_id(DEFAULT_TEMPLATE, "yep-24-123"), Some(123));
+        assert_eq!(extract_id(DEFAULT_TEMPLATE, "123-456-yep"), Some(456));
+        assert_eq!(extract_id(DEFAULT_TEMPLATE, "foobar"), None);
+        assert_eq!(
+            extract_id("{type}/{id}/{slug}", "feature/123/rails-7"),
+            Some(123)
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let templates = [
+            DEFAULT_TEMPLATE,
+            "me/{slug}-{id}",
+            "{conventional_type}/PT-{id}-{slug}",
+            "{type}/{id}/{slug}",
+            "{initials}-{id}-{slug}-v2",
+        ];
+        let names = ["Upgrade to rails 7", "2 widgets, 3 gadgets", "Add a widget"];
+
+        for template in templates {
+            for name in names {
+                let branch = render(template, &parts(name)).unwrap();
+                assert_eq!(extract_id(template, &branch), Some(555), "{}", branch);
+            }
+        }
+
+        // branches from before a template was set still work
+        assert_eq!(
+            extract_id("{conventional_type}/PT-{id}-{slug}", "add-a-widget-555"),
+            Some(555)
+        );
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Café déjà vu", 40, true), "cafe-deja-vu");
+        assert_eq!(slug("Café déjà vu", 40, false), "café-déjà-vu");
+        assert_eq!(
+            slug("Widgets: now with more widgets!", 12, false),
+            "widgets-now"
+        );
+        assert_eq!(
+            slug("Widgets: now with more widgets!", 12, true),
+            "widgets-now"
+        );
+    }
+}
@@ -0,0 +1,730 @@
+use anyhow::{anyhow, Context};
+use indoc::indoc;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+use stories::api::client::DEFAULT_BASE_URL;
+
+use crate::branch_name::DEFAULT_TEMPLATE as DEFAULT_BRANCH_TEMPLATE;
+use crate::summarizer::DEFAULT_AI_BASE_URL;
+
+pub const PROJECT_FILE: &str = "stories.json";
+
+/// A setting that can be read from the config files or the environment
+pub struct Key {
+    /// the name in config files and `stories config`
+    pub name: &'static str,
+    pub env: &'static str,
+    pub kind: Kind,
+}
+
+pub enum Kind {
+    Integer,
+    Boolean,
+    String,
+    OneOf(&'static [&'static str]),
+    /// `alias=id` pairs separated by commas, e.g. `web=1234,api=5678`
+    Projects,
+}
+
+pub const KEYS: &[Key] = &[
+    Key {
+        name: "project_id",
+        env: "STORIES_PROJECT_ID",
+        kind: Kind::Integer,
+    },
+    Key {
+        name: "projects",
+        env: "STORIES_PROJECTS",
+        kind: Kind::Projects,
+    },
+    Key {
+        name: "base_url",
+        env: "STORIES_BASE_URL",
+        kind: Kind::String,
+    },
+    Key {
+        name: "branch_prefix",
+        env: "STORIES_BRANCH_PREFIX",
+        kind: Kind::String,
+    },
+    Key {
+        name: "branch_template",
+        env: "STORIES_BRANCH_TEMPLATE",
+        kind: Kind::String,
+    },
+    Key {
+        name: "branch_slug_length",
+        env: "STORIES_BRANCH_SLUG_LENGTH",
+        kind: Kind::Integer,
+    },
+    Key {
+        name: "branch_transliterate",
+        env: "STORIES_BRANCH_TRANSLITERATE",
+        kind: Kind::Boolean,
+    },
+    Key {
+        name: "ai_model",
+        env: "STORIES_AI_MODEL",
+        kind: Kind::String,
+    },
+    Key {
+        name: "ai_backend",
+        env: "STORIES_AI_BACKEND",
+        kind: Kind::OneOf(&["openai", "fake"]),
+    },
+    Key {
+        name: "ai_base_url",
+        env: "STORIES_AI_BASE_URL",
+        kind: Kind::String,
+    },
+    Key {
+        name: "ai_max_tokens",
+        env: "STORIES_AI_MAX_TOKENS",
+        kind: Kind::Integer,
+    },
+    Key {
+        name: "standup_prompt",
+        env: "STORIES_STANDUP_PROMPT",
+        kind: Kind::String,
+    },
+    Key {
+        name: "pr_template",
+        env: "STORIES_PR_TEMPLATE",
+        kind: Kind::String,
+    },
+    Key {
+        name: "credential_helper",
+        env: "STORIES_CREDENTIAL_HELPER",
+        kind: Kind::String,
+    },
+    Key {
+        name: "output_format",
+        env: "STORIES_OUTPUT_FORMAT",
+        kind: Kind::OneOf(&["text", "json"]),
+    },
+];
+
+impl Key {
+    pub fn find(name: &str) -> anyhow::Result<&'static Key> {
+        KEYS.iter().find(|key| key.name == name).ok_or_else(|| {
+            anyhow!(
+                "unknown config key {:?}, expected one of: {}",
+                name,
+                KEYS.iter()
+                    .map(|key| key.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+    }
+
+    /// Turns a value typed on the command line or in an env var into json
+    pub fn parse(&self, raw: &str) -> anyhow::Result<Value> {
+        match self.kind {
+            Kind::Integer => raw
+                .trim()
+                .parse::<u64>()
+                .map(Value::from)
+                .with_context(|| format!("{} should be a number, not {:?}", self.name, raw)),
+            Kind::Boolean => match raw.trim() {
+                "true" | "yes" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "0" => Ok(Value::Bool(false)),
+                _ => Err(anyhow!(
+                    "{} should be true or false, not {:?}",
+                    self.name,
+                    raw
+                )),
+            },
+            Kind::String => Ok(Value::String(raw.to_string())),
+            Kind::OneOf(choices) if choices.contains(&raw) => Ok(Value::String(raw.to_string())),
+            Kind::OneOf(choices) => Err(anyhow!(
+                "{} should be one of {}, not {:?}",
+                self.name,
+                choices.join(", "),
+                raw
+            )),
+            Kind::Projects => raw
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (alias, id) = pair.split_once('=').ok_or_else(|| {
+                        anyhow!("{} should look like web=1234,api=5678", self.name)
+                    })?;
+                    let id = id
+                        .trim()
+                        .parse::<u64>()
+                        .with_context(|| format!("{:?} isn't a project id", id))?;
+                    Ok((alias.trim().to_string(), Value::from(id)))
+                })
+                .collect::<anyhow::Result<Map<String, Value>>>()
+                .map(Value::Object),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    User(PathBuf),
+    Project(PathBuf),
+    Env(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::User(path) | Source::Project(path) => write!(f, "{}", path.display()),
+            Source::Env(name) => write!(f, "${}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: Value,
+    pub source: Source,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputFormat::Text => write!(f, "text"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// What writes summaries for `--summarize`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AiBackend {
+    /// openai, or anything with an openai compatible api at `ai_base_url`
+    #[default]
+    OpenAi,
+    /// canned answers without going anywhere, for tests
+    Fake,
+}
+
+impl fmt::Display for AiBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AiBackend::OpenAi => write!(f, "openai"),
+            AiBackend::Fake => write!(f, "fake"),
+        }
+    }
+}
+
+/// One of the tracker projects a repository works with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectRef {
+    /// the name it has in `projects`, if it's listed there
+    pub alias: Option<String>,
+    pub id: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Settings {
+    project_id: Option<u64>,
+    #[serde(default)]
+    projects: BTreeMap<String, u64>,
+    base_url: Option<String>,
+    branch_prefix: Option<String>,
+    branch_template: Option<String>,
+    branch_slug_length: Option<usize>,
+    branch_transliterate: Option<bool>,
+    ai_model: Option<String>,
+    #[serde(default)]
+    ai_backend: AiBackend,
+    ai_base_url: Option<String>,
+    ai_max_tokens: Option<u16>,
+    standup_prompt: Option<String>,
+    pr_template: Option<String>,
+    credential_helper: Option<String>,
+    #[serde(default)]
+    output_format: OutputFormat,
+}
+
+/// The effective configuration: ~/.config/stories/config.json, overridden by the nearest
+/// stories.json, overridden by STORIES_* env vars.
+#[derive(Debug)]
+pub struct Config {
+    settings: Settings,
+    entries: BTreeMap<String, Entry>,
+    pub project_file: Option<PathBuf>,
+    pub user_file: PathBuf,
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Config> {
+        let user_file = config_dir()?.join("config.json");
+        Config::load_from(&env::current_dir()?, &user_file, |name| env::var(name).ok())
+    }
+
+    pub fn load_from(
+        dir: &Path,
+        user_file: &Path,
+        env_var: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Config> {
+        let mut entries = BTreeMap::new();
+
+        if user_file.exists() {
+            for (name, value) in read_file(user_file)? {
+                let source = Source::User(user_file.to_path_buf());
+                entries.insert(name, Entry { value, source });
+            }
+        }
+
+        let project_file = find_project_file(dir);
+        if let Some(path) = &project_file {
+            for (name, value) in read_file(path)? {
+                let source = Source::Project(path.clone());
+                entries.insert(name, Entry { value, source });
+            }
+        }
+
+        for key in KEYS {
+            if let Some(raw) = env_var(key.env) {
+                let value = key.parse(&raw)?;
+                let source = Source::Env(key.env);
+                entries.insert(key.name.to_string(), Entry { value, source });
+            }
+        }
+
+        let merged: Map<String, Value> = entries
+            .iter()
+            .map(|(name, entry)| (name.clone(), entry.value.clone()))
+            .collect();
+
+        let settings: Settings = serde_json::from_value(Value::Object(merged))
+            .context("the config isn't right, check it with `stories config list`")?;
+
+        Ok(Config {
+            settings,
+            entries,
+            project_file,
+            user_file: user_file.to_path_buf(),
+        })
+    }
+
+    /// The effective value of `name` as text, including defaults
+    pub fn value(&self, name: &str) -> Option<String> {
+        match name {
+            "project_id" => self.settings.project_id.map(|id| id.to_string()),
+            "projects" if self.settings.projects.is_empty() => None,
+            "projects" => Some(
+                self.settings
+                    .projects
+                    .iter()
+                    .map(|(alias, id)| format!("{}={}", alias, id))
+                    .join(","),
+            ),
+            "base_url" => Some(self.base_url().to_string()),
+            "branch_prefix" => Some(self.branch_prefix().to_string()),
+            "branch_template" => Some(self.branch_template().to_string()),
+            "branch_slug_length" => Some(self.branch_slug_length().to_string()),
+            "branch_transliterate" => Some(self.branch_transliterate().to_string()),
+            "ai_model" => Some(self.ai_model().to_string()),
+            "ai_backend" => Some(self.ai_backend().to_string()),
+            "ai_base_url" => Some(self.ai_base_url().to_string()),
+            "ai_max_tokens" => self.ai_max_tokens().map(|n| n.to_string()),
+            "standup_prompt" => self.settings.standup_prompt.clone(),
+            "pr_template" => self.settings.pr_template.clone(),
+            "credential_helper" => self.settings.credential_helper.clone(),
+            "output_format" => Some(self.output_format().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Where the effective value of `name` came from
+    pub fn source(&self, name: &str) -> Source {
+        self.entries
+            .get(name)
+            .map(|entry| entry.source.clone())
+            .unwrap_or(Source::Default)
+    }
+
+    /// Whether `name` was set by a stories.json, which comes with whatever repository was
+    /// cloned rather than from the user
+    pub fn set_by_project(&self, name: &str) -> bool {
+        matches!(self.source(name), Source::Project(_))
+    }
+
+    /// Paths in config files are relative to the file, and ones from the environment are
+    /// relative to the current directory
+    fn relative_to_source(&self, name: &str, path: &Path) -> PathBuf {
+        match self.source(name) {
+            Source::User(file) | Source::Project(file) if path.is_relative() => file
+                .parent()
+                .map(|dir| dir.join(path))
+                .unwrap_or_else(|| path.to_path_buf()),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// The project to use when one isn't picked with --project: `project_id`, or the only
+    /// entry in `projects`
+    pub fn project_id(&self) -> anyhow::Result<u64> {
+        if let Some(project_id) = self.settings.project_id {
+            return Ok(project_id);
+        }
+
+        match self.settings.projects.values().collect::<Vec<_>>().as_slice() {
+            [] => Err(anyhow!(indoc! {r#"
+                didn't find a stories.json in this directory or its parents.
+
+                add one with something like this:
+
+                    $ echo '{"project_id":123456}' > stories.json
+
+                to find a project id, visit the project in the tracker website and look at the url
+                https://www.pivotaltracker.com/dashboard
+            "#})),
+            [project_id] => Ok(**project_id),
+            _ => Err(anyhow!(
+                "stories.json lists several projects, pick one with --project {}, or set a default project_id",
+                self.settings.projects.keys().join("|")
+            )),
+        }
+    }
+
+    /// Every configured project, the default first
+    pub fn projects(&self) -> Vec<ProjectRef> {
+        let mut projects: Vec<ProjectRef> = self
+            .settings
+            .projects
+            .iter()
+            .map(|(alias, id)| ProjectRef {
+                alias: Some(alias.clone()),
+                id: *id,
+            })
+            .collect();
+
+        if let Some(project_id) = self.settings.project_id {
+            match projects.iter().position(|project| project.id == project_id) {
+                Some(index) => {
+                    let default = projects.remove(index);
+                    projects.insert(0, default);
+                }
+                None => projects.insert(
+                    0,
+                    ProjectRef {
+                        alias: None,
+                        id: project_id,
+                    },
+                ),
+            }
+        }
+
+        projects
+    }
+
+    /// Finds a project by its alias in `projects`, or takes a plain project id as is
+    pub fn select_project(&self, name: &str) -> anyhow::Result<u64> {
+        if let Some(project_id) = self.settings.projects.get(name) {
+            return Ok(*project_id);
+        }
+
+        name.parse().map_err(|_| {
+            anyhow!(
+                "there's no project called {:?} in stories.json, expected a project id or one of: {}",
+                name,
+                self.settings.projects.keys().join(", ")
+            )
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.settings
+            .base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_BASE_URL)
+    }
+
+    pub fn branch_prefix(&self) -> &str {
+        self.settings.branch_prefix.as_deref().unwrap_or_default()
+    }
+
+    /// How `stories branch` names branches, after the `branch_prefix`
+    pub fn branch_template(&self) -> &str {
+        self.settings
+            .branch_template
+            .as_deref()
+            .unwrap_or(DEFAULT_BRANCH_TEMPLATE)
+    }
+
+    pub fn branch_slug_length(&self) -> usize {
+        self.settings.branch_slug_length.unwrap_or(40)
+    }
+
+    /// Whether slugs spell accents and other scripts out in ascii
+    pub fn branch_transliterate(&self) -> bool {
+        self.settings.branch_transliterate.unwrap_or(true)
+    }
+
+    pub fn ai_model(&self) -> &str {
+        self.settings.ai_model.as_deref().unwrap_or("gpt-3.5-turbo")
+    }
+
+    pub fn ai_backend(&self) -> AiBackend {
+        self.settings.ai_backend
+    }
+
+    /// Where an openai compatible api is, e.g. `http://localhost:11434/v1` for ollama
+    pub fn ai_base_url(&self) -> &str {
+        self.settings
+            .ai_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_AI_BASE_URL)
+    }
+
+    /// A cap on how long summaries can be, in place of the limit each command picks
+    pub fn ai_max_tokens(&self) -> Option<u16> {
+        self.settings.ai_max_tokens
+    }
+
+    /// Instructions for `activity --summarize`, in place of the built in ones
+    pub fn standup_prompt(&self) -> Option<&str> {
+        self.settings.standup_prompt.as_deref()
+    }
+
+    /// The file `stories pr body` fills in, if there is one
+    pub fn pr_template(&self) -> Option<PathBuf> {
+        let path = self.settings.pr_template.as_deref()?;
+        Some(self.relative_to_source("pr_template", Path::new(path)))
+    }
+
+    /// A command that stores api tokens, like a git credential helper. It's run with the
+    /// tokens, so one from a stories.json is never used.
+    pub fn credential_helper(&self) -> Option<&str> {
+        if self.set_by_project("credential_helper") {
+            return None;
+        }
+        self.settings.credential_helper.as_deref()
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.settings.output_format
+    }
+}
+
+pub fn config_dir() -> anyhow::Result<PathBuf> {
+    let home = env::var("HOME").context("no $HOME env var defined? wacky")?;
+    let path = Path::new(&home).join(".config/stories");
+    Ok(path)
+}
+
+/// Looks for stories.json in `dir` and its parents, stopping at the root of the git repository
+fn find_project_file(dir: &Path) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        let candidate = ancestor.join(PROJECT_FILE);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+
+        if ancestor.join(".git").exists() {
+            return None;
+        }
+    }
+
+    None
+}
+
+fn read_file(path: &Path) -> anyhow::Result<Map<String, Value>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    serde_json::from_str(&contents).with_context(|| format!("{} isn't right", path.display()))
+}
+
+/// Sets `name` in the config file at `path`, creating it if needed and keeping its other keys
+pub fn set(path: &Path, name: &str, raw: &str) -> anyhow::Result<()> {
+    let value = Key::find(name)?.parse(raw)?;
+
+    let mut contents = if path.exists() {
+        read_file(path)?
+    } else {
+        Map::new()
+    };
+    contents.insert(name.to_string(), value);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(
+        path,
+        format!("{}\n", serde_json::to_string_pretty(&contents)?),
+    )
+    .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_walks_up_to_stories_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let project_file = dir.path().join("repo/stories.json");
+        write(&project_file, r#"{"project_id": 1}"#);
+        fs::create_dir_all(dir.path().join("repo/.git")).unwrap();
+
+        let nested = dir.path().join("repo/src/nested");
+        fs::create_dir_all(&nested).unwrap();
+
+        let config = Config::load_from(&nested, &dir.path().join("none.json"), |_| None).unwrap();
+        assert_eq!(config.project_id().unwrap(), 1);
+        assert_eq!(config.project_file, Some(project_file));
+    }
+
+    #[test]
+    fn test_stops_at_repository_root() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("stories.json"), r#"{"project_id": 1}"#);
+        fs::create_dir_all(dir.path().join("repo/.git")).unwrap();
+
+        let config = Config::load_from(
+            &dir.path().join("repo"),
+            &dir.path().join("none.json"),
+            |_| None,
+        )
+        .unwrap();
+        assert!(config.project_id().is_err());
+        assert_eq!(config.project_file, None);
+    }
+
+    #[test]
+    fn test_layering() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_file = dir.path().join("home/config.json");
+        write(
+            &user_file,
+            r#"{"branch_prefix": "me/", "ai_model": "gpt-4", "base_url": "http://user"}"#,
+        );
+        write(
+            &dir.path().join("repo/stories.json"),
+            r#"{"project_id": 1, "branch_prefix": "team/"}"#,
+        );
+
+        let config = Config::load_from(&dir.path().join("repo"), &user_file, |name| {
+            (name == "STORIES_BASE_URL").then(|| "http://env".to_string())
+        })
+        .unwrap();
+
+        assert_eq!(config.project_id().unwrap(), 1);
+        assert_eq!(config.branch_prefix(), "team/");
+        assert_eq!(config.ai_model(), "gpt-4");
+        assert_eq!(config.base_url(), "http://env");
+        assert_eq!(config.output_format(), OutputFormat::Text);
+
+        assert_eq!(config.source("ai_model"), Source::User(user_file));
+        assert_eq!(config.source("base_url"), Source::Env("STORIES_BASE_URL"));
+        assert_eq!(config.source("output_format"), Source::Default);
+    }
+
+    #[test]
+    fn test_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        let none = dir.path().join("none.json");
+        write(
+            &dir.path().join("stories.json"),
+            r#"{"projects": {"web": 1, "api": 2, "infra": 3}}"#,
+        );
+
+        let config = Config::load_from(dir.path(), &none, |_| None).unwrap();
+        assert!(config.project_id().is_err());
+        assert_eq!(config.select_project("api").unwrap(), 2);
+        assert_eq!(config.select_project("42").unwrap(), 42);
+        assert!(config.select_project("mobile").is_err());
+        assert_eq!(
+            config.projects().iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![2, 3, 1]
+        );
+
+        let config = Config::load_from(dir.path(), &none, |name| {
+            (name == "STORIES_PROJECT_ID").then(|| "3".to_string())
+        })
+        .unwrap();
+        assert_eq!(config.project_id().unwrap(), 3);
+        assert_eq!(
+            config.projects()[0],
+            ProjectRef {
+                alias: Some("infra".to_string()),
+                id: 3
+            }
+        );
+
+        set(
+            &dir.path().join("stories.json"),
+            "projects",
+            "web=10, api=20",
+        )
+        .unwrap();
+        let config = Config::load_from(dir.path(), &none, |_| None).unwrap();
+        assert_eq!(config.value("projects").unwrap(), "api=20,web=10");
+    }
+
+    #[test]
+    fn test_ignores_project_credential_helper() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_file = dir.path().join("home/config.json");
+        write(
+            &dir.path().join("stories.json"),
+            r#"{"credential_helper": "curl attacker"}"#,
+        );
+
+        let config = Config::load_from(dir.path(), &user_file, |_| None).unwrap();
+        assert!(config.set_by_project("credential_helper"));
+        assert_eq!(config.credential_helper(), None);
+
+        write(&user_file, r#"{"credential_helper": "pass-helper"}"#);
+        fs::remove_file(dir.path().join("stories.json")).unwrap();
+        let config = Config::load_from(dir.path(), &user_file, |_| None).unwrap();
+        assert_eq!(config.credential_helper(), Some("pass-helper"));
+
+        let config = Config::load_from(dir.path(), &user_file, |name| {
+            (name == "STORIES_CREDENTIAL_HELPER").then(|| "env-helper".to_string())
+        })
+        .unwrap();
+        assert_eq!(config.credential_helper(), Some("env-helper"));
+    }
+
+    #[test]
+    fn test_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stories.json");
+        write(&path, r#"{"project_id": 1}"#);
+
+        set(&path, "branch_prefix", "feat/").unwrap();
+        set(&path, "project_id", "2").unwrap();
+
+        assert!(set(&path, "project_id", "two").is_err());
+        assert!(set(&path, "output_format", "yaml").is_err());
+        assert!(set(&path, "colour", "blue").is_err());
+
+        let config =
+            Config::load_from(dir.path(), &dir.path().join("none.json"), |_| None).unwrap();
+        assert_eq!(config.project_id().unwrap(), 2);
+        assert_eq!(config.branch_prefix(), "feat/");
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Context};
+use std::{
+    env, fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Service {
+    Tracker,
+    OpenAi,
+}
+
+impl Service {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Service::Tracker => "tracker",
+            Service::OpenAi => "openai",
+        }
+    }
+
+    pub fn env_var(&self) -> &'static str {
+        match self {
+            Service::Tracker => "STORIES_TRACKER_TOKEN",
+            Service::OpenAi => "OPENAI_API_KEY",
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self {
+            Service::Tracker => "tracker_api_token.txt",
+            Service::OpenAi => "open_ai_secret_key.txt",
+        }
+    }
+
+    fn login_help(&self) -> String {
+        match self {
+            Service::Tracker => format!(
+                "1. visit https://www.pivotaltracker.com/profile#api\n\
+                 2. note that token!\n\
+                 3. run `stories auth login` and paste it in, or set ${}",
+                self.env_var()
+            ),
+            Service::OpenAi => format!(
+                "1. visit https://platform.openai.com/account/api-keys\n\
+                 2. create a new secret\n\
+                 3. run `stories auth login --openai` and paste it in, or set ${}",
+                self.env_var()
+            ),
+        }
+    }
+}
+
+/// Where a secret was found, or was saved to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Env(&'static str),
+    Helper(String),
+    File(PathBuf),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env(name) => write!(f, "${}", name),
+            Source::Helper(helper) => write!(f, "the credential helper `{}`", helper),
+            Source::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Credential {
+    pub secret: String,
+    pub source: Source,
+}
+
+/// Api tokens, looked up in the environment, then a credential helper if there is one, then
+/// files in the config directory that only their owner can read.
+///
+/// A credential helper is a command that works like one of git's: it's run with `get`, `store`
+/// or `erase`, given `service=<name>` (and `password=<secret>` to store) on stdin, and prints
+/// `password=<secret>` for `get`.
+pub struct Credentials {
+    dir: PathBuf,
+    helper: Option<String>,
+}
+
+impl Credentials {
+    pub fn new(dir: impl Into<PathBuf>, helper: Option<String>) -> Self {
+        Credentials {
+            dir: dir.into(),
+            helper,
+        }
+    }
+
+    pub fn get(&self, service: Service) -> anyhow::Result<Option<Credential>> {
+        if let Some(secret) = env::var(service.env_var())
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+        {
+            return Ok(Some(Credential {
+                secret: secret.trim().to_string(),
+                source: Source::Env(service.env_var()),
+            }));
+        }
+
+        if let Some(helper) = &self.helper {
+            let output = run_helper(helper, "get", service, None)?;
+            let secret = output
+                .lines()
+                .find_map(|line| line.strip_prefix("password="))
+                .map(|secret| secret.trim().to_string())
+                .filter(|secret| !secret.is_empty());
+
+            if let Some(secret) = secret {
+                return Ok(Some(Credential {
+                    secret,
+                    source: Source::Helper(helper.clone()),
+                }));
+            }
+        }
+
+        let path = self.path(service);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        check_permissions(&path)?;
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        Ok(Some(Credential {
+            secret: contents.trim().to_string(),
+            source: Source::File(path),
+        }))
+    }
+
+    /// Like `get`, but explains how to log in when there's nothing to be found
+    pub fn read(&self, service: Service) -> anyhow::Result<Credential> {
+        self.get(service)?.ok_or_else(|| {
+            anyhow!(
+                "not logged in to {}\n\n{}",
+                service.name(),
+                service.login_help()
+            )
+        })
+    }
+
+    /// Saves with the credential helper if there is one, otherwise to a file only the owner
+    /// can read
+    pub fn save(&self, service: Service, secret: &str) -> anyhow::Result<Source> {
+        if let Some(helper) = &self.helper {
+            run_helper(helper, "store", service, Some(secret))?;
+            return Ok(Source::Helper(helper.clone()));
+        }
+
+        let path = self.path(service);
+        write_private(&path, &format!("{}\n", secret))?;
+        Ok(Source::File(path))
+    }
+
+    /// Removes a saved secret, returning where it was removed from
+    pub fn remove(&self, service: Service) -> anyhow::Result<Option<Source>> {
+        if let Some(helper) = &self.helper {
+            run_helper(helper, "erase", service, None)?;
+            return Ok(Some(Source::Helper(helper.clone())));
+        }
+
+        let path = self.path(service);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+        Ok(Some(Source::File(path)))
+    }
+
+    fn path(&self, service: Service) -> PathBuf {
+        self.dir.join(service.file_name())
+    }
+}
+
+fn run_helper(
+    helper: &str,
+    action: &str,
+    service: Service,
+    secret: Option<&str>,
+) -> anyhow::Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", helper))
+        .arg("sh")
+        .arg(action)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run the credential helper ({})", helper))?;
+
+    let mut input = format!("service={}\n", service.name());
+    if let Some(secret) = secret {
+        input.push_str(&format!("password={}\n", secret));
+    }
+    // helpers that don't need what's on stdin can exit without reading it
+    match child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())
+    {
+        Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => return Err(err.into()),
+        _ => {}
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "the credential helper ({} {}) exited with {}",
+            helper,
+            action,
+            output.status
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> anyhow::Result<()> {
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o004 != 0 {
+        return Err(anyhow!(
+            "{path} is readable by anyone, so it isn't being used. make it private with:\n\n    $ chmod 600 {path}",
+            path = path.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+fn write_private(path: &Path, contents: &str) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    // the mode only applies to new files
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_is_private() {
+        let dir = tempfile::tempdir().unwrap();
+        let credentials = Credentials::new(dir.path(), None);
+
+        let source = credentials.save(Service::Tracker, "sk-secret").unwrap();
+        let path = dir.path().join("tracker_api_token.txt");
+        assert_eq!(source, Source::File(path.clone()));
+
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        let credential = credentials.get(Service::Tracker).unwrap().unwrap();
+        assert_eq!(credential.secret, "sk-secret");
+
+        assert_eq!(
+            credentials.remove(Service::Tracker).unwrap(),
+            Some(Source::File(path))
+        );
+        assert!(credentials.get(Service::Tracker).unwrap().is_none());
+        assert!(credentials.remove(Service::Tracker).unwrap().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_refuses_world_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tracker_api_token.txt");
+        fs::write(&path, "sk-secret").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let err = Credentials::new(dir.path(), None)
+            .get(Service::Tracker)
+            .unwrap_err();
+        assert!(err.to_string().contains("chmod 600"));
+    }
+
+    #[test]
+    fn test_helper() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = dir.path().join("store");
+        let helper = dir.path().join("helper.sh");
+        fs::write(
+            &helper,
+            format!(
+                "case $1 in\n  get) cat {store} 2>/dev/null || true ;;\n  store) grep password= > {store} ;;\n  erase) rm -f {store} ;;\nesac\n",
+                store = store.display()
+            ),
+        )
+        .unwrap();
+
+        let helper = format!("sh {}", helper.display());
+        let credentials = Credentials::new(dir.path(), Some(helper.clone()));
+
+        assert!(credentials.get(Service::Tracker).unwrap().is_none());
+
+        credentials.save(Service::Tracker, "sk-helper").unwrap();
+        assert_eq!(fs::read_to_string(&store).unwrap(), "password=sk-helper\n");
+
+        let credential = credentials.get(Service::Tracker).unwrap().unwrap();
+        assert_eq!(credential.secret, "sk-helper");
+        assert_eq!(credential.source, Source::Helper(helper));
+
+        credentials.remove(Service::Tracker).unwrap();
+        assert!(!store.exists());
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Context};
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Opens `initial` in the user's $VISUAL or $EDITOR and returns what they saved.
+///
+/// The editor command is run through the shell so that values like `code --wait` work.
+pub fn edit(initial: &str) -> anyhow::Result<String> {
+    let path = draft_path();
+    fs::write(&path, initial).with_context(|| format!("failed to write {}", path.display()))?;
+
+    let result = run_editor(&path);
+    let contents = fs::read_to_string(&path);
+    fs::remove_file(&path).ok();
+
+    result?;
+    Ok(contents?)
+}
+
+fn run_editor(path: &PathBuf) -> anyhow::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("failed to launch your editor ({})", editor))?;
+
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", editor, status));
+    }
+
+    Ok(())
+}
+
+/// Saves `text` to a new file in `dir` and returns where. The file is never one that was
+/// already there, so a symlink left in its place can't redirect it.
+pub fn save_draft(dir: &Path, text: &str) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let path = dir.join(format!(
+        "{}-{}.md",
+        chrono::Local::now().format("%Y-%m-%d-%H%M%S"),
+        std::process::id()
+    ));
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    Ok(path)
+}
+
+fn draft_path() -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+
+    env::temp_dir().join(format!("stories-{}-{}.md", std::process::id(), nanos))
+}
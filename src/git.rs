@@ -0,0 +1,490 @@
+//! Finding the repository and its current branch reads `.git` directly, so it works without
+//! git installed. Everything else, like listing and switching branches, finding the hooks
+//! directory or diffing a branch, runs the git CLI.
+
+use anyhow::{anyhow, Context};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Just enough of a git repository to tell which branch is checked out, read straight from
+/// `.git`
+#[derive(Debug)]
+pub struct Repository {
+    /// where HEAD lives: `.git` for a regular checkout, or somewhere under the main repository's
+    /// `.git` for worktrees and submodules
+    pub git_dir: PathBuf,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Head {
+    Branch(String),
+    Detached(String),
+}
+
+impl Repository {
+    /// Finds the repository `start` is in, walking up parent directories and following the
+    /// `gitdir:` files that worktrees and submodules use in place of a `.git` directory.
+    /// $GIT_DIR wins if it's set, like it does for git.
+    pub fn discover(start: &Path) -> anyhow::Result<Repository> {
+        if let Some(git_dir) = env::var_os("GIT_DIR") {
+            return Ok(Repository {
+                git_dir: start.join(git_dir),
+            });
+        }
+
+        for dir in start.ancestors() {
+            let dot_git = dir.join(".git");
+
+            if dot_git.is_dir() {
+                return Ok(Repository { git_dir: dot_git });
+            }
+
+            if dot_git.is_file() {
+                return Ok(Repository {
+                    git_dir: read_gitdir_file(&dot_git)?,
+                });
+            }
+        }
+
+        Err(anyhow!(
+            "{} isn't in a git repository",
+            start.to_string_lossy()
+        ))
+    }
+
+    pub fn head(&self) -> anyhow::Result<Head> {
+        let path = self.git_dir.join("HEAD");
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+
+        match branch_name(&contents) {
+            Some(branch) => Ok(Head::Branch(branch)),
+            None => Ok(Head::Detached(contents.trim().to_string())),
+        }
+    }
+
+    /// The checked out branch. When HEAD is detached, e.g. mid-rebase or after checking out a
+    /// commit, this is the branch being rebased or the last branch that was checked out.
+    pub fn current_branch(&self) -> anyhow::Result<Option<String>> {
+        match self.head()? {
+            Head::Branch(branch) => Ok(Some(branch)),
+            Head::Detached(_) => Ok(self.rebasing_branch().or_else(|| self.reflog_branch())),
+        }
+    }
+
+    fn rebasing_branch(&self) -> Option<String> {
+        ["rebase-merge/head-name", "rebase-apply/head-name"]
+            .iter()
+            .filter_map(|path| fs::read_to_string(self.git_dir.join(path)).ok())
+            .find_map(|contents| {
+                contents
+                    .trim()
+                    .strip_prefix("refs/heads/")
+                    .map(str::to_string)
+            })
+    }
+
+    /// Finds the most recent "checkout: moving from <branch> to <commit>" entry in HEAD's reflog
+    fn reflog_branch(&self) -> Option<String> {
+        let reflog = fs::read_to_string(self.git_dir.join("logs/HEAD")).ok()?;
+
+        reflog.lines().rev().find_map(|line| {
+            let (_, message) = line.split_once('\t')?;
+            let (from, _) = message
+                .strip_prefix("checkout: moving from ")?
+                .split_once(" to ")?;
+
+            if is_object_id(from) {
+                None
+            } else {
+                Some(from.to_string())
+            }
+        })
+    }
+}
+
+/// Reads a `.git` file, which points at the real git dir like `gitdir: ../.git/worktrees/name`
+fn read_gitdir_file(path: &Path) -> anyhow::Result<PathBuf> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.to_string_lossy()))?;
+
+    let git_dir = contents
+        .trim()
+        .strip_prefix("gitdir:")
+        .ok_or_else(|| anyhow!("expected {} to have a gitdir: line", path.to_string_lossy()))?
+        .trim();
+
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    Ok(base.join(git_dir))
+}
+
+pub fn branch_name(head_contents: &str) -> Option<String> {
+    Some(
+        head_contents
+            .strip_prefix("ref: refs/heads/")?
+            .trim()
+            .to_string(),
+    )
+}
+
+fn is_object_id(s: &str) -> bool {
+    s.len() >= 7 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// What the checked out branch changes since it forked from its base
+#[derive(Debug)]
+pub struct BranchChanges {
+    pub base: String,
+    /// commit subjects, oldest first
+    pub commits: Vec<String>,
+    /// `git diff --stat`
+    pub stat: String,
+    pub diff: String,
+}
+
+/// Reads the branch's commits and diff against the merge base with `base`, or with
+/// origin's default branch, main or master when there's no `base`. Unlike the rest of this
+/// module, this needs the git cli.
+pub fn branch_changes(dir: &Path, base: Option<&str>) -> anyhow::Result<BranchChanges> {
+    let base = match base {
+        Some(base) => base.to_string(),
+        None => default_base(dir)?,
+    };
+
+    let merge_base = git(dir, &["merge-base", &base, "HEAD"])
+        .with_context(|| format!("couldn't find where HEAD forked from {}", base))?;
+    let merge_base = merge_base.trim();
+    let range = format!("{}..HEAD", merge_base);
+
+    let commits = git(dir, &["log", "--reverse", "--format=%s", &range])?
+        .lines()
+        .map(str::to_string)
+        .collect();
+
+    Ok(BranchChanges {
+        commits,
+        stat: git(dir, &["diff", "--stat", merge_base, "HEAD"])?,
+        diff: git(dir, &["diff", merge_base, "HEAD"])?,
+        base,
+    })
+}
+
+/// The local branches of the repository `dir` is in, most recently committed to first
+pub fn local_branches(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let branches = git(
+        dir,
+        &[
+            "for-each-ref",
+            "--sort=-committerdate",
+            "--format=%(refname:short)",
+            "refs/heads",
+        ],
+    )?;
+    Ok(branches.lines().map(str::to_string).collect())
+}
+
+/// Checks out `branch`, making it first if `create`
+pub fn switch(dir: &Path, branch: &str, create: bool) -> anyhow::Result<()> {
+    if create {
+        git(dir, &["switch", "-c", branch])?;
+    } else {
+        git(dir, &["switch", branch])?;
+    }
+    Ok(())
+}
+
+/// Where git looks for hooks in the repository `dir` is in, honoring core.hooksPath
+pub fn hooks_dir(dir: &Path) -> anyhow::Result<PathBuf> {
+    let hooks = git(dir, &["rev-parse", "--git-path", "hooks"])?;
+    Ok(dir.join(hooks.trim()))
+}
+
+fn default_base(dir: &Path) -> anyhow::Result<String> {
+    if let Ok(origin_head) = git(
+        dir,
+        &["symbolic-ref", "--short", "refs/remotes/origin/HEAD"],
+    ) {
+        return Ok(origin_head.trim().to_string());
+    }
+
+    ["main", "master"]
+        .iter()
+        .find(|branch| git(dir, &["rev-parse", "--verify", "--quiet", branch]).is_ok())
+        .map(|branch| branch.to_string())
+        .ok_or_else(|| {
+            anyhow!("couldn't tell which branch this one is based on, pass it with --base")
+        })
+}
+
+fn git(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("failed to run git")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Shortens a diff to about `max_length` bytes so it fits in a prompt. Files are kept whole
+/// while they fit, then cut off, then left out with a note saying how many are missing.
+pub fn truncate_diff(diff: &str, max_length: usize) -> String {
+    if diff.len() <= max_length {
+        return diff.to_string();
+    }
+
+    let mut files = vec![];
+    let mut rest = diff;
+    while let Some(next) = rest[1..].find("\ndiff --git ") {
+        let (file, remaining) = rest.split_at(next + 2);
+        files.push(file);
+        rest = remaining;
+    }
+    files.push(rest);
+
+    let mut out = String::new();
+    for (index, file) in files.iter().enumerate() {
+        let room = max_length.saturating_sub(out.len());
+        if file.len() <= room {
+            out.push_str(file);
+        } else if room >= 200 {
+            // leaves room for the notes
+            let mut end = room - 40;
+            while !file.is_char_boundary(end) {
+                end -= 1;
+            }
+            let cut = file[..end].rfind('\n').map(|i| i + 1).unwrap_or(end);
+            out.push_str(&file[..cut]);
+            out.push_str("... (cut short)\n");
+        } else {
+            out.push_str(&format!("... ({} more files)\n", files.len() - index));
+            break;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args([
+                "-c",
+                "init.defaultBranch=main",
+                "-c",
+                "protocol.file.allow=always",
+            ])
+            .args(args)
+            .current_dir(dir)
+            .env_remove("GIT_DIR")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    fn repo_with_commit(dir: &Path) {
+        git(dir, &["init", "--quiet"]);
+        git(
+            dir,
+            &["commit", "--quiet", "--allow-empty", "-m", "initial"],
+        );
+    }
+
+    fn current_branch(dir: &Path) -> Option<String> {
+        Repository::discover(dir).unwrap().current_branch().unwrap()
+    }
+
+    #[test]
+    fn test_branch_name() {
+        assert_eq!(
+            branch_name("ref: refs/heads/main"),
+            Some("main".to_string())
+        );
+        assert_eq!(
+            branch_name("4b825dc642cb6eb9a060e54bf8d69288fbee4904"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_subdirectory() {
+        let dir = tempfile::tempdir().unwrap();
+        repo_with_commit(dir.path());
+        git(dir.path(), &["switch", "--quiet", "-c", "a-story-123"]);
+
+        let nested = dir.path().join("src/deeply/nested");
+        fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(current_branch(&nested), Some("a-story-123".to_string()));
+    }
+
+    #[test]
+    fn test_not_a_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Repository::discover(dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_worktree() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main");
+        fs::create_dir_all(&main).unwrap();
+        repo_with_commit(&main);
+
+        let worktree = dir.path().join("worktree");
+        git(
+            &main,
+            &[
+                "worktree",
+                "add",
+                "--quiet",
+                "-b",
+                "worktree-story-456",
+                worktree.to_str().unwrap(),
+            ],
+        );
+
+        assert!(worktree.join(".git").is_file());
+        assert_eq!(
+            current_branch(&worktree),
+            Some("worktree-story-456".to_string())
+        );
+        assert_eq!(current_branch(&main), Some("main".to_string()));
+    }
+
+    #[test]
+    fn test_submodule() {
+        let dir = tempfile::tempdir().unwrap();
+        let library = dir.path().join("library");
+        fs::create_dir_all(&library).unwrap();
+        repo_with_commit(&library);
+
+        let app = dir.path().join("app");
+        fs::create_dir_all(&app).unwrap();
+        repo_with_commit(&app);
+        git(&app, &["switch", "--quiet", "-c", "app-story-1"]);
+        git(
+            &app,
+            &[
+                "submodule",
+                "add",
+                "--quiet",
+                library.to_str().unwrap(),
+                "vendor/library",
+            ],
+        );
+
+        let submodule = app.join("vendor/library");
+        git(&submodule, &["switch", "--quiet", "-c", "library-story-2"]);
+
+        assert_eq!(
+            current_branch(&submodule),
+            Some("library-story-2".to_string())
+        );
+        assert_eq!(current_branch(&app), Some("app-story-1".to_string()));
+    }
+
+    #[test]
+    fn test_detached_head() {
+        let dir = tempfile::tempdir().unwrap();
+        repo_with_commit(dir.path());
+        git(
+            dir.path(),
+            &["switch", "--quiet", "-c", "detached-story-789"],
+        );
+        git(
+            dir.path(),
+            &["commit", "--quiet", "--allow-empty", "-m", "more"],
+        );
+        git(dir.path(), &["switch", "--quiet", "--detach", "HEAD~1"]);
+
+        let repo = Repository::discover(dir.path()).unwrap();
+        assert!(matches!(repo.head().unwrap(), Head::Detached(_)));
+        assert_eq!(
+            repo.current_branch().unwrap(),
+            Some("detached-story-789".to_string())
+        );
+    }
+
+    #[test]
+    fn test_branch_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        repo_with_commit(dir.path());
+        git(dir.path(), &["switch", "--quiet", "-c", "widget-story-555"]);
+        fs::write(dir.path().join("widget.txt"), "a widget\n").unwrap();
+        git(dir.path(), &["add", "widget.txt"]);
+        git(dir.path(), &["commit", "--quiet", "-m", "add a widget"]);
+        git(
+            dir.path(),
+            &["commit", "--quiet", "--allow-empty", "-m", "polish it"],
+        );
+
+        let changes = branch_changes(dir.path(), None).unwrap();
+        assert_eq!(changes.base, "main");
+        assert_eq!(changes.commits, ["add a widget", "polish it"]);
+        assert!(changes.stat.contains("widget.txt"));
+        assert!(changes.diff.contains("+a widget"));
+    }
+
+    #[test]
+    fn test_truncate_diff() {
+        let file = |name: &str, lines: usize| {
+            format!(
+                "diff --git a/{name} b/{name}\n--- a/{name}\n+++ b/{name}\n{}",
+                "+line\n".repeat(lines)
+            )
+        };
+        let diff = [file("a", 10), file("b", 100), file("c", 10), file("d", 10)].concat();
+
+        assert_eq!(truncate_diff(&diff, diff.len()), diff);
+
+        let truncated = truncate_diff(&diff, 400);
+        assert!(truncated.len() <= 400, "{}", truncated);
+        assert!(truncated.starts_with(&file("a", 10)));
+        assert!(truncated.contains("diff --git a/b b/b\n"));
+        assert!(truncated.contains("... (cut short)\n"));
+        assert!(truncated.ends_with("... (2 more files)\n"), "{}", truncated);
+    }
+
+    #[test]
+    fn test_rebasing() {
+        let dir = tempfile::tempdir().unwrap();
+        repo_with_commit(dir.path());
+        git(dir.path(), &["switch", "--quiet", "--detach"]);
+
+        let rebase_dir = dir.path().join(".git/rebase-merge");
+        fs::create_dir_all(&rebase_dir).unwrap();
+        fs::write(
+            rebase_dir.join("head-name"),
+            "refs/heads/rebasing-story-321\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            current_branch(dir.path()),
+            Some("rebasing-story-321".to_string())
+        );
+    }
+}
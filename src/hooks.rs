@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Context};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+/// The hooks `stories hooks install` writes
+pub const HOOKS: [&str; 2] = ["prepare-commit-msg", "commit-msg"];
+
+/// Marks a hook as one of ours, so it's safe to replace or remove
+const MARKER: &str = "# installed by stories";
+
+/// How commits refer to their story, which decides what tracker does with it
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// `[#12345]`, which only links the commit
+    Id,
+    /// `[finishes #12345]`, which also finishes the story
+    Finishes,
+    /// `[delivers #12345]`, which also delivers the story once it's merged
+    Delivers,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Id => "id",
+            Mode::Finishes => "finishes",
+            Mode::Delivers => "delivers",
+        }
+    }
+
+    pub fn reference(&self, story_id: u64) -> String {
+        match self {
+            Mode::Id => format!("[#{}]", story_id),
+            Mode::Finishes => format!("[finishes #{}]", story_id),
+            Mode::Delivers => format!("[delivers #{}]", story_id),
+        }
+    }
+}
+
+/// A hook that hands itself back to the stories binary at `exe`
+fn script(exe: &Path, hook: &str, mode: Mode) -> String {
+    format!(
+        "#!/bin/sh\n{marker}, remove with `stories hooks uninstall`\nexec '{exe}' hooks run {hook} --mode {mode} \"$@\"\n",
+        marker = MARKER,
+        exe = exe.display().to_string().replace('\'', r"'\''"),
+        hook = hook,
+        mode = mode.name(),
+    )
+}
+
+fn is_ours(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|contents| contents.contains(MARKER))
+}
+
+/// Writes the hooks to `hooks_dir`, refusing to replace hooks that aren't ours unless `force`
+pub fn install(
+    hooks_dir: &Path,
+    exe: &Path,
+    mode: Mode,
+    force: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let paths: Vec<PathBuf> = HOOKS.iter().map(|hook| hooks_dir.join(hook)).collect();
+
+    if !force {
+        if let Some(theirs) = paths.iter().find(|path| path.exists() && !is_ours(path)) {
+            return Err(anyhow!(
+                "there's already a {} hook, replace it with --force",
+                theirs.display()
+            ));
+        }
+    }
+
+    fs::create_dir_all(hooks_dir)
+        .with_context(|| format!("failed to create {}", hooks_dir.display()))?;
+
+    for (hook, path) in HOOKS.iter().zip(&paths) {
+        fs::write(path, script(exe, hook, mode))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        #[cfg(unix)]
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(paths)
+}
+
+/// Removes our hooks from `hooks_dir`, leaving anyone else's alone
+pub fn uninstall(hooks_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+
+    for hook in HOOKS {
+        let path = hooks_dir.join(hook);
+        if is_ours(&path) {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+            removed.push(path);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Whether the message, ignoring git's comment lines, already refers to the story
+pub fn mentions(message: &str, story_id: u64) -> bool {
+    let needle = format!("#{}", story_id);
+
+    message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .any(|line| {
+            line.match_indices(&needle)
+                .any(|(at, _)| !line[at + needle.len()..].starts_with(|c: char| c.is_ascii_digit()))
+        })
+}
+
+/// Whether the message, ignoring git's comment lines, says nothing but `reference`
+pub fn is_empty(message: &str, reference: &str) -> bool {
+    message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .all(|line| line.replace(reference, "").trim().is_empty())
+}
+
+/// Adds `reference` at the end of the message, above the comments git shows in the editor
+pub fn stamp(message: &str, reference: &str) -> String {
+    let comments_at = message
+        .match_indices('\n')
+        .map(|(at, _)| at + 1)
+        .chain([0])
+        .filter(|&at| message[at..].starts_with('#'))
+        .min()
+        .unwrap_or(message.len());
+    let (body, comments) = message.split_at(comments_at);
+
+    let mut stamped = format!("{}\n\n{}\n", body.trim_end(), reference);
+    if !comments.is_empty() {
+        stamped.push('\n');
+        stamped.push_str(comments);
+    }
+    stamped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mentions() {
+        assert!(mentions("add a widget\n\n[delivers #555]\n", 555));
+        assert!(mentions("add a widget [#555]", 555));
+        assert!(!mentions("add a widget [#5555]", 555));
+        assert!(!mentions("add a widget\n# [#555] is in a comment\n", 555));
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(is_empty(
+            "\n\n[#555]\n\n# Please enter the commit message\n",
+            "[#555]"
+        ));
+        assert!(is_empty("", "[#555]"));
+        assert!(!is_empty("add a widget\n\n[#555]\n", "[#555]"));
+    }
+
+    #[test]
+    fn test_stamp() {
+        assert_eq!(
+            stamp("add a widget\n", "[delivers #555]"),
+            "add a widget\n\n[delivers #555]\n"
+        );
+        assert_eq!(
+            stamp("\n# Please enter the commit message\n#\n", "[#555]"),
+            "\n\n[#555]\n\n# Please enter the commit message\n#\n"
+        );
+        assert_eq!(
+            stamp(
+                "add a widget\n\nwith words\n\n# Please enter\n",
+                "[finishes #555]"
+            ),
+            "add a widget\n\nwith words\n\n[finishes #555]\n\n# Please enter\n"
+        );
+    }
+
+    #[test]
+    fn test_install() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks_dir = dir.path().join("hooks");
+        let exe = Path::new("/usr/local/bin/stories");
+
+        let installed = install(&hooks_dir, exe, Mode::Finishes, false).unwrap();
+        assert_eq!(installed.len(), 2);
+        let script = fs::read_to_string(hooks_dir.join("commit-msg")).unwrap();
+        assert!(script
+            .contains("exec '/usr/local/bin/stories' hooks run commit-msg --mode finishes \"$@\""));
+
+        // reinstalling replaces our own hooks
+        install(&hooks_dir, exe, Mode::Id, false).unwrap();
+
+        fs::write(hooks_dir.join("commit-msg"), "#!/bin/sh\nlint\n").unwrap();
+        assert!(install(&hooks_dir, exe, Mode::Id, false).is_err());
+
+        assert_eq!(
+            uninstall(&hooks_dir).unwrap(),
+            [hooks_dir.join("prepare-commit-msg")]
+        );
+        assert!(hooks_dir.join("commit-msg").exists());
+    }
+}
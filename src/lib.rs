@@ -0,0 +1,5 @@
+#![forbid(unsafe_code)]
+
+//! A small client for the pivotal tracker v5 api, shared by the `stories` cli.
+
+pub mod api;
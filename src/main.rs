@@ -6,8 +6,7 @@
 // but is still very amateur level stuff and thrown together without much
 // consideration for aesthetics.
 
-use api::schema::{StoryState, StoryType};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
 use clap::{Args, Parser, Subcommand};
 use colored::*;
 use itertools::Itertools;
@@ -15,9 +14,11 @@ use lazy_static::lazy_static;
 use pulldown_cmark_mdcat::resources::NoopResourceHandler;
 use pulldown_cmark_mdcat::{push_tty, Environment, Settings, TerminalProgram, TerminalSize, Theme};
 use regex::Regex;
-use serde::Deserialize;
-use serde_json::{Map, Number, Value};
-use slugify::slugify;
+use stories::api::client::DEFAULT_BASE_URL;
+use stories::api::schema::{
+    Activity, LabelName, StoryCreate, StoryDetail, StoryState, StoryType, StoryUpdate, Task,
+};
+use stories::api::{self, Cache, TrackerClient, TrackerError};
 
 use pulldown_cmark::Options;
 use syntect::parsing::SyntaxSet;
@@ -26,24 +27,38 @@ use tabled::style::{Style, VerticalLine};
 use tabled::{Modify, Table, Tabled, Width};
 
 use anyhow::{anyhow, Context, Result};
-use reqwest::header;
-use sha256::digest;
 use terminal_link::Link;
 
-use async_openai::types::{
-    ChatCompletionRequestMessageArgs, CreateChatCompletionRequestArgs, Role,
-};
-
 use indoc::indoc;
+mod branch_name;
+mod config;
+mod credentials;
+mod editor;
+mod git;
+mod hooks;
+mod report;
+mod story_document;
+mod summarizer;
+mod template;
+
+use config::{config_dir, AiBackend, Config, OutputFormat, ProjectRef};
+use credentials::{Credentials, Service};
+use story_document::StoryDocument;
+use summarizer::{Backend, Request, Summarizer, DEFAULT_AI_BASE_URL};
+
 use std::{
+    collections::HashMap,
     env,
     fs::{self},
-    path::{Path, PathBuf},
-    process::Command,
+    io::{Read, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
 };
 
-pub mod api;
-
 #[derive(Tabled, Debug)]
 struct StoryRow {
     #[tabled(rename = "Id")]
@@ -63,8 +78,32 @@ struct StoryRow {
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Use cached tracker data, however old, instead of going online
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Use this branch name to find the current story, instead of the checked out git branch
+    #[arg(long, global = true, value_name = "NAME")]
+    branch: Option<String>,
+
+    /// Use this project, by its alias in stories.json or its id, instead of the default
+    #[arg(long, global = true, value_name = "PROJECT")]
+    project: Option<String>,
 }
 
+/// Set once from --offline or $STORIES_OFFLINE, read whenever a client is made
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Set once from --branch, read whenever the current story is looked up
+static BRANCH_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+/// Set once from --project, read whenever the project is looked up
+static PROJECT_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+/// The tracker token, once it's been read, so a credential helper only runs once a command
+static API_TOKEN: OnceLock<String> = OnceLock::new();
+
 #[derive(clap::ValueEnum, Clone)]
 enum PrField {
     Body,
@@ -77,13 +116,44 @@ enum Commands {
     #[clap(alias = "show")]
     View(ViewArgs),
 
-    /// Displays the project
+    /// Comments on the current story
+    Comment(CommentArgs),
+
+    /// Lists and checks off the current story's tasks
+    Task(TaskArgs),
+
+    /// Displays the project's velocity and current iteration
     Project(ProjectArgs),
 
+    /// Lists the stories in the current iteration, the backlog, the icebox or iteration N
+    #[clap(alias = "it")]
+    Iteration(IterationArgs),
+
+    /// Creates a new story, written in your $EDITOR
+    New(NewArgs),
+
+    /// Edits the current story in your $EDITOR
+    Edit(EditArgs),
+
     /// Checks out a git branch and changes the story's state to started
     #[clap(alias = "br")]
     Branch(BranchArgs),
 
+    /// Changes the story's state to started
+    Start(TransitionArgs),
+
+    /// Changes the story's state to finished
+    Finish(TransitionArgs),
+
+    /// Changes the story's state to delivered
+    Deliver(TransitionArgs),
+
+    /// Changes the story's state to accepted
+    Accept(TransitionArgs),
+
+    /// Changes the story's state to rejected, and comments with the reason why
+    Reject(RejectArgs),
+
     /// Print out suggested pull request title or body. Aliased as `pr`
     ///
     /// Pairs well with the github's "gh" cli https://cli.github.com/
@@ -96,18 +166,26 @@ enum Commands {
     /// Stories assigned to you
     Mine(MineArgs),
 
+    /// Finds stories with tracker's search syntax, or by label, state, type and owner
+    Search(SearchArgs),
+
     /// Currently authenticated user
     Whoami {},
 
     /// Recent things you have done on tracker
     Activity(ActivityArgs),
-    // future commands:
-    // - cache clear (handle changes to tracker Me record)
-}
 
-#[derive(Debug, Deserialize)]
-struct ProjectConfig {
-    project_id: u64,
+    /// Inspect or clear cached tracker data
+    Cache(CacheArgs),
+
+    /// Shows or changes settings, and where they come from
+    Config(ConfigArgs),
+
+    /// Logs in to tracker or openai, or shows which account is in use
+    Auth(AuthArgs),
+
+    /// Installs git hooks that add the branch's story to commit messages
+    Hooks(HooksArgs),
 }
 
 fn print_result(result: Result<(), anyhow::Error>) {
@@ -128,28 +206,88 @@ fn print_result(result: Result<(), anyhow::Error>) {
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    OFFLINE.store(
+        cli.offline || env::var_os("STORIES_OFFLINE").is_some(),
+        Ordering::Relaxed,
+    );
+
+    if let Some(branch) = &cli.branch {
+        BRANCH_OVERRIDE.set(branch.clone()).ok();
+    }
+
+    if let Some(project) = &cli.project {
+        PROJECT_OVERRIDE.set(project.clone()).ok();
+    }
+
     match &cli.command {
         Some(Commands::View(args)) => {
             print_result(view(args).await);
         }
+        Some(Commands::Comment(args)) => {
+            print_result(comment(args).await);
+        }
+        Some(Commands::Task(args)) => {
+            print_result(task(args).await);
+        }
         Some(Commands::Project(args)) => {
             print_result(project(args).await);
         }
+        Some(Commands::Iteration(args)) => {
+            print_result(iteration(args).await);
+        }
         Some(Commands::Mine(args)) => {
             print_result(mine(args).await);
         }
+        Some(Commands::Search(args)) => {
+            print_result(search(args).await);
+        }
         Some(Commands::Whoami {}) => {
             print_result(whoami().await);
         }
+        Some(Commands::New(args)) => {
+            print_result(new(args).await);
+        }
+        Some(Commands::Edit(args)) => {
+            print_result(edit(args).await);
+        }
         Some(Commands::Branch(args)) => {
             print_result(branch(args).await);
         }
+        Some(Commands::Start(args)) => {
+            print_result(transition(args, StoryState::Started, None).await);
+        }
+        Some(Commands::Finish(args)) => {
+            print_result(transition(args, StoryState::Finished, None).await);
+        }
+        Some(Commands::Deliver(args)) => {
+            print_result(transition(args, StoryState::Delivered, None).await);
+        }
+        Some(Commands::Accept(args)) => {
+            print_result(transition(args, StoryState::Accepted, None).await);
+        }
+        Some(Commands::Reject(args)) => {
+            print_result(
+                transition(&args.transition, StoryState::Rejected, Some(&args.reason)).await,
+            );
+        }
         Some(Commands::PullRequest(args)) => {
             print_result(pull_request(args).await);
         }
         Some(Commands::Activity(activity_args)) => {
             print_result(activity(activity_args).await);
         }
+        Some(Commands::Cache(args)) => {
+            print_result(cache(args).await);
+        }
+        Some(Commands::Config(args)) => {
+            print_result(config(args));
+        }
+        Some(Commands::Auth(args)) => {
+            print_result(auth(args).await);
+        }
+        Some(Commands::Hooks(args)) => {
+            print_result(hooks(args));
+        }
 
         None => {}
     }
@@ -166,8 +304,13 @@ pub struct PrArgs {
     story_id: Option<String>,
 
     #[arg(short, long)]
-    /// Automatically summarize the field with chatgpt
+    /// Automatically summarize the field with chatgpt, the body from the branch's commits and diff
     summarize: bool,
+
+    /// The branch to compare with for the body's summary, otherwise origin's default branch,
+    /// main or master
+    #[arg(long, requires = "summarize")]
+    base: Option<String>,
 }
 
 pub async fn pull_request(pr_args: &PrArgs) -> anyhow::Result<()> {
@@ -176,39 +319,58 @@ pub async fn pull_request(pr_args: &PrArgs) -> anyhow::Result<()> {
         None => read_branch_id()?,
     };
 
-    let project_id = read_project_id()?;
-    let story_url = format!(
-        "https://www.pivotaltracker.com/services/v5/projects/{}/stories/{}",
-        project_id, story_id
-    );
-
-    let client = tracker_api_client().await?;
-    let story: api::schema::StoryDetail = client.get(&story_url).send().await?.json().await?;
+    let client = tracker_api_client()?;
+    let project_id = story_project_id(&client, story_id).await?;
+    let story = client.get_story(project_id, story_id).await?;
 
-    let conventional_commit_type = match &story.story_type {
-        StoryType::Bug => "fix",
-        StoryType::Feature => "feat",
-        StoryType::Chore => "chore",
-        StoryType::Release => "chore",
-    };
+    let conventional_commit_type = conventional_type(&story.story_type);
 
     match pr_args.field {
         PrField::Body => {
-            let message = format!(
-                indoc! {r#"
-                    {}
+            let template = match read_config()?.pr_template() {
+                Some(path) => fs::read_to_string(&path).with_context(|| {
+                    format!("failed to read the pr_template {}", path.display())
+                })?,
+                None => PR_TEMPLATE.to_string(),
+            };
 
-                    --------
+            let tasks = if template.contains("{tasks}") {
+                client.list_tasks(project_id, story_id).await?
+            } else {
+                vec![]
+            };
+
+            let summary = if pr_args.summarize {
+                let changes = git::branch_changes(&env::current_dir()?, pr_args.base.as_deref())?;
+                let summary = summarizer(None)?
+                    .summarize(Request {
+                        instructions: PR_BODY_PROMPT,
+                        prompt: pr_body_prompt(&story, &changes),
+                        max_tokens: 500,
+                    })
+                    .await?;
+                Some(summary)
+            } else {
+                None
+            };
 
-                    Tracker: [delivers #{}]"#},
-                story.url, story.id,
+            let body = pr_body(
+                &template,
+                &story,
+                &tasks,
+                summary.as_deref().unwrap_or_default(),
             );
-            println!("{}", message);
+            match summary {
+                // templates without a spot for the summary get it at the top
+                Some(summary) if !template.contains("{summary}") => {
+                    println!("{}\n\n{}", summary, body.trim_end())
+                }
+                _ => println!("{}", body.trim_end()),
+            }
             Ok(())
         }
         PrField::Title => {
             if pr_args.summarize {
-                let client = open_ai_client()?;
                 let action = match &story.story_type {
                     StoryType::Bug => "fixes",
                     StoryType::Feature => "implements",
@@ -228,29 +390,15 @@ pub async fn pull_request(pr_args: &PrArgs) -> anyhow::Result<()> {
                         .unwrap_or("(description missing)".to_string())
                 );
 
-                let request = CreateChatCompletionRequestArgs::default()
-                    .model("gpt-3.5-turbo")
-                    .messages([
-                        ChatCompletionRequestMessageArgs::default()
-                            .role(Role::Assistant)
-                            .content( "You summarize tasks into git commit messages. You follow the git conventional commit specification by prefixing features and bugs with feat: and fix: respectively, and omit the optional scope.")
-                            .build()?,
-                        ChatCompletionRequestMessageArgs::default()
-                            .role(Role::User)
-                            .content(full_prompt)
-                            .build()?,
-                    ])
-                    .max_tokens(40_u16)
-                    .build()?;
-
-                let response = client.chat().create(request).await?;
-
-                let first_choice = response
-                    .choices
-                    .first()
-                    .ok_or_else(|| anyhow!("didn't get a choice back from openai"))?;
-
-                println!("{}", first_choice.message.content);
+                let title = summarizer(None)?
+                    .summarize(Request {
+                        instructions: "You summarize tasks into git commit messages. You follow the git conventional commit specification by prefixing features and bugs with feat: and fix: respectively, and omit the optional scope.",
+                        prompt: full_prompt,
+                        max_tokens: 40,
+                    })
+                    .await?;
+
+                println!("{}", title);
             } else {
                 println!("{}: {}", conventional_commit_type, story.name);
             }
@@ -259,30 +407,117 @@ pub async fn pull_request(pr_args: &PrArgs) -> anyhow::Result<()> {
     }
 }
 
-fn open_ai_client() -> anyhow::Result<async_openai::Client> {
-    let api_key = read_open_ai_secret_key()?;
-    Ok(async_openai::Client::new().with_api_key(api_key))
+const PR_TEMPLATE: &str = indoc! {"
+    {url}
+
+    --------
+
+    Tracker: {trailer}"};
+
+const PR_BODY_PROMPT: &str = indoc! {"
+    You write pull request descriptions from a tracker story and the branch's commits and diff.
+    Reply with three short markdown sections: What changed, Why, and How to test. Why comes from
+    the story, the others from the commits and diff. Only describe what's actually there.
+"};
+
+/// How much of the diff goes in the prompt for a pull request body, to leave room in the
+/// model's context for the story, the commits and the answer
+const DIFF_LENGTH: usize = 12_000;
+
+fn pr_body_prompt(story: &StoryDetail, changes: &git::BranchChanges) -> String {
+    format!(
+        "Story: {} ({})\n{}\n\nCommits:\n{}\n\nFiles changed compared to {}:\n{}\nDiff:\n{}",
+        story.name,
+        story.story_type,
+        story
+            .description
+            .as_deref()
+            .unwrap_or("(description missing)"),
+        changes
+            .commits
+            .iter()
+            .map(|commit| format!("- {}", commit))
+            .join("\n"),
+        changes.base,
+        changes.stat,
+        git::truncate_diff(&changes.diff, DIFF_LENGTH)
+    )
 }
 
-fn read_open_ai_secret_key() -> anyhow::Result<String> {
-    let path = Path::new(&config_dir()?).join("open_ai_secret_key.txt");
-
-    let path_string = path.as_os_str().to_str().unwrap();
-
-    let token_file_contents = fs::read_to_string(&path).context(format!(
-        indoc! {"
-            didn't find the credentials config file at {}
-
-            1. visit https://platform.openai.com/account/api-keys
-            2. create a new secret
-            3. run something like this to dump it in
+/// Fills in a pull request body template with the story's {name}, {type}, {estimate},
+/// {labels}, {description}, {tasks} as a checklist, {url}, {id}, the tracker {trailer} and
+/// the {summary} written by --summarize
+fn pr_body(template: &str, story: &StoryDetail, tasks: &[Task], summary: &str) -> String {
+    let story_type = story.story_type.to_string();
+    let estimate = story.estimate.map(|e| e.to_string()).unwrap_or_default();
+    let labels = story.labels.iter().map(|label| &label.name).join(", ");
+    let tasks = tasks
+        .iter()
+        .map(|task| {
+            let check = if task.complete { "x" } else { " " };
+            format!("- [{}] {}", check, task.description)
+        })
+        .join("\n");
+    let id = story.id.to_string();
+    let trailer = format!("[delivers #{}]", story.id);
+
+    template::render(
+        template,
+        &[
+            ("name", &story.name),
+            ("type", &story_type),
+            ("estimate", &estimate),
+            ("labels", &labels),
+            (
+                "description",
+                story.description.as_deref().unwrap_or_default(),
+            ),
+            ("tasks", &tasks),
+            ("url", &story.url),
+            ("id", &id),
+            ("trailer", &trailer),
+            ("summary", summary),
+        ],
+    )
+}
 
-                $ echo $YOUR_API_TOKEN > {}
-        "},
-        path_string, path_string
-    ))?;
+/// The configured summarizer, using `model` in place of the ai_model setting if it's given
+fn summarizer(model: Option<&str>) -> anyhow::Result<Summarizer> {
+    let config = read_config()?;
+    let model = model.unwrap_or(config.ai_model());
+
+    let summarizer = match config.ai_backend() {
+        AiBackend::Fake => Summarizer::new(Backend::Fake, model),
+        AiBackend::OpenAi
+            if config.ai_base_url() != DEFAULT_AI_BASE_URL
+                && config.set_by_project("ai_base_url") =>
+        {
+            // a stories.json comes with the repository, so it doesn't get to send the key
+            // anywhere but openai
+            eprintln!(
+                "{}",
+                format!(
+                    "not sending your openai key to {}, which is set in {}",
+                    config.ai_base_url(),
+                    config.source("ai_base_url")
+                )
+                .yellow()
+            );
+            Summarizer::open_ai(config.ai_base_url(), None, model)
+        }
+        AiBackend::OpenAi => {
+            let credentials = credentials()?;
+            let api_key = match credentials.get(Service::OpenAi)? {
+                Some(credential) => Some(credential.secret),
+                // servers other than openai's, like a local ollama, usually don't need a key
+                None if config.ai_base_url() != DEFAULT_AI_BASE_URL => None,
+                None => Some(credentials.read(Service::OpenAi)?.secret),
+            };
+            Summarizer::open_ai(config.ai_base_url(), api_key, model)
+        }
+    };
 
-    Ok(token_file_contents.trim().to_string())
+    Ok(summarizer.max_tokens(config.ai_max_tokens()))
 }
 
 pub async fn whoami() -> anyhow::Result<()> {
@@ -291,397 +526,1788 @@ pub async fn whoami() -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn tracker_api_client() -> anyhow::Result<reqwest::Client> {
-    static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
-
-    let token = read_api_token()?;
-
-    let mut headers = header::HeaderMap::new();
-    let mut api_token_value = header::HeaderValue::from_str(&token)?;
-    api_token_value.set_sensitive(true);
-
-    headers.insert("X-TrackerToken", api_token_value);
-
-    let client = reqwest::Client::builder()
-        .user_agent(APP_USER_AGENT)
-        .default_headers(headers)
-        .build()?;
+pub fn tracker_api_client() -> anyhow::Result<TrackerClient> {
+    let token = read_api_token()?;
+    let cache = Cache::new(cache_dir()?).offline(OFFLINE.load(Ordering::Relaxed));
+    Ok(tracker_client(&token)?.with_cache(cache))
+}
+
+/// A client for the configured base url. A stories.json comes with the repository, so a
+/// base_url from one doesn't get sent the token unless it's tracker itself.
+fn tracker_client(token: &str) -> anyhow::Result<TrackerClient> {
+    let config = read_config()?;
+    let base_url = config.base_url();
+
+    if base_url != DEFAULT_BASE_URL && config.set_by_project("base_url") {
+        eprintln!(
+            "{}",
+            format!(
+                "not sending your tracker token to {}, which is set in {}",
+                base_url,
+                config.source("base_url")
+            )
+            .yellow()
+        );
+        return Ok(TrackerClient::with_base_url("", base_url)?);
+    }
+
+    Ok(TrackerClient::with_base_url(token, base_url)?)
+}
+
+#[derive(Args)]
+pub struct NewArgs {
+    /// Check out a branch and start the story once it's created
+    #[arg(short, long)]
+    start: bool,
+}
+
+pub async fn new(new_args: &NewArgs) -> anyhow::Result<()> {
+    let project_id = read_project_id()?;
+    let client = tracker_api_client()?;
+
+    let text = editor::edit(&StoryDocument::template())?;
+    let document = StoryDocument::parse(&text).map_err(|err| keep_draft(err, &text))?;
+
+    let owner_ids =
+        resolve_owner_ids(&client, project_id, &document.owners.unwrap_or_default()).await?;
+
+    let story = StoryCreate {
+        name: document.name,
+        story_type: document.story_type,
+        description: Some(document.description).filter(|d| !d.is_empty()),
+        estimate: document.estimate,
+        labels: document
+            .labels
+            .into_iter()
+            .map(|name| LabelName { name })
+            .collect(),
+        owner_ids,
+    };
+
+    let data = client
+        .create_story(project_id, &story)
+        .await
+        .map_err(|err| keep_draft(err.into(), &text))?;
+
+    println!("Created story: #{} {}", data.id, data.name.italic());
+    println!("{}\n", data.url.truecolor(200, 200, 200));
+
+    let start = new_args.start
+        || (atty::is(atty::Stream::Stdin) && confirm("Check out a branch and start it?")?);
+
+    if start {
+        let branch_args = BranchArgs {
+            story_id: data.id.to_string(),
+            name: None,
+            estimate: None,
+        };
+        branch(&branch_args).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    /// Optionally provide a story id, otherwise find it in the current git branch
+    story_id: Option<String>,
+}
+
+pub async fn edit(edit_args: &EditArgs) -> anyhow::Result<()> {
+    let story_id = match &edit_args.story_id {
+        Some(id) => parse_story_id(id)?,
+        None => read_branch_id()?,
+    };
+
+    let client = tracker_api_client()?;
+    let project_id = story_project_id(&client, story_id).await?;
+
+    let story = client.get_story(project_id, story_id).await?;
+    let original = StoryDocument::from(&story);
+
+    let text = editor::edit(&original.render())?;
+    let document = StoryDocument::parse(&text).map_err(|err| keep_draft(err, &text))?;
+
+    // the document starts without an owners line, so one that's there was added to reassign
+    // the story
+    let mut changes = document.changes_from(&original);
+    if let Some(owners) = &document.owners {
+        changes.owner_ids = Some(
+            resolve_owner_ids(&client, project_id, owners)
+                .await
+                .map_err(|err| keep_draft(err, &text))?,
+        );
+    }
+    if changes.is_empty() {
+        println!("No changes to #{} {}", story.id, story.name.italic());
+        return Ok(());
+    }
+
+    let data = client
+        .update_story(project_id, story_id, &changes)
+        .await
+        .map_err(|err| keep_draft(err.into(), &text))?;
+
+    println!("Updated story: #{} {}", data.id, data.name.italic());
+
+    Ok(())
+}
+
+/// Saves what was written in the editor somewhere it can be recovered from, so a typo in the
+/// front matter or a rejected change doesn't throw the whole thing away
+fn keep_draft(err: anyhow::Error, text: &str) -> anyhow::Error {
+    match config_dir().and_then(|dir| editor::save_draft(&dir.join("drafts"), text)) {
+        Ok(draft) => err.context(format!("your draft was saved to {}", draft.display())),
+        Err(_) => err,
+    }
+}
+
+/// Finds the ids for people given by initials, username, name or "me"
+async fn resolve_owner_ids(
+    client: &TrackerClient,
+    project_id: u64,
+    owners: &[String],
+) -> anyhow::Result<Vec<u64>> {
+    if owners.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let me = tracker_me().await?;
+    let memberships = if owners.iter().any(|owner| owner != "me") {
+        client.project_memberships(project_id).await?
+    } else {
+        vec![]
+    };
+
+    owners
+        .iter()
+        .map(|owner| {
+            if owner == "me" {
+                return Ok(me.id);
+            }
+
+            memberships
+                .iter()
+                .map(|membership| &membership.person)
+                .find(|person| {
+                    person.initials.eq_ignore_ascii_case(owner)
+                        || person.username.eq_ignore_ascii_case(owner)
+                        || person.name.eq_ignore_ascii_case(owner)
+                })
+                .map(|person| person.id)
+                .ok_or_else(|| anyhow!("nobody in the project goes by {:?}", owner))
+        })
+        .collect()
+}
+
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+
+    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[derive(Args)]
+pub struct BranchArgs {
+    story_id: String,
+
+    /// Name the branch after this instead of the story name, e.g. a shorter version of it
+    #[arg(short, long)]
+    name: Option<String>,
+
+    #[arg(short, long)]
+    estimate: Option<u8>,
+}
+
+pub async fn branch(branch_args: &BranchArgs) -> anyhow::Result<()> {
+    let client = tracker_api_client()?;
+    let story_id = parse_story_id(&branch_args.story_id)?;
+    let project_id = story_project_id(&client, story_id).await?;
+    let me = tracker_me().await?;
+
+    let data = client.get_story(project_id, story_id).await?;
+
+    // git goes first, so tracker is left alone when there's a problem with the branch
+    let config = read_config()?;
+    let template = branch_template(&config);
+    let dir = env::current_dir()?;
+    let existing = git::local_branches(&dir)?
+        .into_iter()
+        .find(|branch| branch_name::extract_id(&template, branch) == Some(story_id));
+
+    let git_message = match existing {
+        Some(branch) => {
+            git::switch(&dir, &branch, false)?;
+            format!("switched to {}", branch.bold())
+        }
+        None => {
+            let story_type = data.story_type.to_string();
+            let branch = branch_name::render(
+                &template,
+                &branch_name::Parts {
+                    story_type: &story_type,
+                    conventional_type: conventional_type(&data.story_type),
+                    slug: branch_name::slug(
+                        branch_args.name.as_deref().unwrap_or(&data.name),
+                        config.branch_slug_length(),
+                        config.branch_transliterate(),
+                    ),
+                    id: data.id.into(),
+                    initials: &me.initials,
+                },
+            )?;
+            git::switch(&dir, &branch, true)?;
+            format!("checked out {}", branch.bold())
+        }
+    };
+
+    let started_by_me =
+        data.current_state == StoryState::Started && data.owner_ids.contains(&me.id);
+
+    if started_by_me && branch_args.estimate.is_none() {
+        println!("Already started: #{} {}", data.id, data.name.italic());
+    } else {
+        let changes = StoryUpdate {
+            current_state: (!started_by_me).then_some(StoryState::Started),
+            owner_ids: (!started_by_me).then(|| vec![me.id]),
+            estimate: branch_args.estimate.map(|estimate| Some(estimate.into())),
+            ..Default::default()
+        };
+
+        let data = update_story(&client, project_id, story_id, changes).await?;
+        println!("Updated story: #{} {}", data.id, data.name.italic());
+    }
+
+    println!("Git branch: {}\n", git_message);
+
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct TransitionArgs {
+    /// Optionally provide a story id, otherwise find it in the current git branch
+    story_id: Option<String>,
+
+    /// Estimate the story while changing its state
+    #[arg(short, long)]
+    estimate: Option<u32>,
+}
+
+#[derive(Args)]
+pub struct RejectArgs {
+    #[command(flatten)]
+    transition: TransitionArgs,
+
+    /// Why the story is being rejected, posted as a comment on the story
+    #[arg(short, long)]
+    reason: String,
+}
+
+pub async fn transition(
+    args: &TransitionArgs,
+    next_state: StoryState,
+    comment: Option<&str>,
+) -> anyhow::Result<()> {
+    let story_id = match &args.story_id {
+        Some(id) => parse_story_id(id)?,
+        None => read_branch_id()?,
+    };
+
+    let client = tracker_api_client()?;
+    let project_id = story_project_id(&client, story_id).await?;
+
+    let story = client.get_story(project_id, story_id).await?;
+
+    if !story
+        .current_state
+        .can_transition_to(&story.story_type, &next_state)
+    {
+        return Err(anyhow!(
+            "#{} is a {} story that's {}, it can't be {}",
+            story.id,
+            format_story_type(&story.story_type).to_lowercase(),
+            story.current_state,
+            next_state
+        ));
+    }
+
+    let mut changes = StoryUpdate {
+        current_state: Some(next_state),
+        estimate: args.estimate.map(Some),
+        ..Default::default()
+    };
+
+    if next_state == StoryState::Started && story.owner_ids.is_empty() {
+        changes.owner_ids = Some(vec![tracker_me().await?.id]);
+    }
+
+    // the comment goes first, so a rejection never ends up without its reason
+    if let Some(text) = comment {
+        client.create_comment(project_id, story_id, text).await?;
+    }
+
+    let data = update_story(&client, project_id, story_id, changes).await?;
+
+    println!("Updated story: #{} {}", data.id, data.name.italic());
+    println!("{}", format_current_state(&data.current_state));
+
+    Ok(())
+}
+
+/// Updates a story, asking for an estimate and retrying if tracker refuses the change because the
+/// story needs one
+async fn update_story(
+    client: &TrackerClient,
+    project_id: u64,
+    story_id: u64,
+    mut changes: StoryUpdate,
+) -> anyhow::Result<api::schema::StoryDetail> {
+    match client.update_story(project_id, story_id, &changes).await {
+        Err(TrackerError::Api { error, .. }) if error.validation_error("estimate").is_some() => {
+            let problem = &error.validation_error("estimate").unwrap().problem;
+            eprintln!("{}", problem.yellow());
+            changes.estimate = Some(Some(prompt_estimate().context(problem.clone())?));
+            Ok(client.update_story(project_id, story_id, &changes).await?)
+        }
+        result => Ok(result?),
+    }
+}
+
+fn prompt_estimate() -> anyhow::Result<u32> {
+    eprint!("Estimate: ");
+    std::io::stderr().flush()?;
+
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input)? == 0 {
+        return Err(anyhow!("no estimate provided"));
+    }
+
+    input
+        .trim()
+        .parse::<u32>()
+        .with_context(|| format!("{:?} isn't a valid estimate", input.trim()))
+}
+
+async fn tracker_me() -> anyhow::Result<api::schema::Me> {
+    Ok(tracker_api_client()?.me().await?)
+}
+
+#[derive(Args)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheCommands,
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Removes everything from the cache
+    Clear,
+
+    /// Lists the cached responses
+    #[clap(alias = "list")]
+    Ls,
+
+    /// Summarizes what's in the cache
+    Stats,
+}
+
+async fn cache(cache_args: &CacheArgs) -> anyhow::Result<()> {
+    let cache = Cache::new(cache_dir()?);
+
+    match cache_args.command {
+        CacheCommands::Clear => {
+            cache.clear().await?;
+            println!("cleared {}", cache.dir().display());
+        }
+        CacheCommands::Ls => {
+            #[derive(Tabled)]
+            struct CacheRow {
+                #[tabled(rename = "Resource")]
+                resource: &'static str,
+                #[tabled(rename = "Age")]
+                age: String,
+                #[tabled(rename = "Size")]
+                size: String,
+                #[tabled(rename = "Url")]
+                url: String,
+            }
+
+            let rows: Vec<CacheRow> = cache
+                .entries()
+                .await
+                .into_iter()
+                .sorted_by_key(|(_, entry)| (entry.resource, entry.url.clone()))
+                .map(|(metadata, entry)| CacheRow {
+                    resource: entry.resource.name(),
+                    age: format_age(entry.age()),
+                    size: format_size(metadata.size),
+                    url: entry.url,
+                })
+                .collect();
+
+            if rows.is_empty() {
+                println!("the cache is empty");
+            } else {
+                println!("{}", Table::new(&rows).with(Style::modern()));
+            }
+        }
+        CacheCommands::Stats => {
+            #[derive(Tabled)]
+            struct StatsRow {
+                #[tabled(rename = "Resource")]
+                resource: &'static str,
+                #[tabled(rename = "Entries")]
+                entries: usize,
+                #[tabled(rename = "Size")]
+                size: String,
+                #[tabled(rename = "Oldest")]
+                oldest: String,
+                #[tabled(rename = "Ttl")]
+                ttl: String,
+            }
+
+            let entries = cache.entries().await;
+            let rows: Vec<StatsRow> = entries
+                .iter()
+                .into_group_map_by(|(_, entry)| entry.resource)
+                .into_iter()
+                .sorted_by_key(|(resource, _)| *resource)
+                .map(|(resource, group)| StatsRow {
+                    resource: resource.name(),
+                    entries: group.len(),
+                    size: format_size(group.iter().map(|(metadata, _)| metadata.size).sum()),
+                    oldest: group
+                        .iter()
+                        .map(|(_, entry)| entry.age())
+                        .max()
+                        .map(format_age)
+                        .unwrap_or_default(),
+                    ttl: format_age(cache.policy().ttl(resource)),
+                })
+                .collect();
+
+            println!("{}", cache.dir().display());
+            println!(
+                "{} entries, {}\n",
+                entries.len(),
+                format_size(entries.iter().map(|(metadata, _)| metadata.size).sum())
+            );
+            if !rows.is_empty() {
+                println!("{}", Table::new(&rows).with(Style::modern()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommands,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Prints the effective value of a setting
+    Get { key: String },
+
+    /// Saves a setting to the project's stories.json
+    Set {
+        key: String,
+        value: String,
+
+        /// Save it to ~/.config/stories/config.json instead, for every project
+        #[arg(short, long)]
+        user: bool,
+    },
+
+    /// Lists every setting, its value and where it came from
+    #[clap(alias = "ls")]
+    List,
+}
+
+fn config(config_args: &ConfigArgs) -> anyhow::Result<()> {
+    let config = read_config()?;
+
+    match &config_args.command {
+        ConfigCommands::Get { key } => {
+            let key = config::Key::find(key)?;
+            let value = config
+                .value(key.name)
+                .ok_or_else(|| anyhow!("{} isn't set", key.name))?;
+            println!("{}", value);
+        }
+        ConfigCommands::Set { key, value, user } => {
+            if key == "credential_helper" && !user {
+                return Err(anyhow!(
+                    "a credential_helper in stories.json isn't used, save it with --user"
+                ));
+            }
+
+            let path = match (user, &config.project_file) {
+                (true, _) => config.user_file.clone(),
+                (false, Some(project_file)) => project_file.clone(),
+                (false, None) => env::current_dir()?.join(config::PROJECT_FILE),
+            };
+
+            config::set(&path, key, value)?;
+            println!("set {} to {} in {}", key, value, path.display());
+        }
+        ConfigCommands::List => {
+            #[derive(Tabled)]
+            struct ConfigRow {
+                #[tabled(rename = "Key")]
+                key: &'static str,
+                #[tabled(rename = "Value")]
+                value: String,
+                #[tabled(rename = "Source")]
+                source: String,
+            }
+
+            let rows: Vec<ConfigRow> = config::KEYS
+                .iter()
+                .map(|key| ConfigRow {
+                    key: key.name,
+                    value: config.value(key.name).unwrap_or_default(),
+                    source: match config.value(key.name) {
+                        Some(_) => config.source(key.name).to_string(),
+                        None => "not set".to_string(),
+                    },
+                })
+                .collect();
+
+            println!("{}", Table::new(&rows).with(Style::modern()));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct AuthArgs {
+    #[command(subcommand)]
+    command: AuthCommands,
+}
+
+#[derive(Subcommand)]
+enum AuthCommands {
+    /// Saves an api token read from stdin, once tracker has accepted it
+    Login {
+        /// Save an openai api key instead, for --summarize
+        #[arg(long)]
+        openai: bool,
+    },
+
+    /// Removes a saved api token
+    Logout {
+        /// Remove the openai api key instead
+        #[arg(long)]
+        openai: bool,
+    },
+
+    /// Shows who you're logged in as, and where the tokens come from
+    Status,
+}
+
+#[derive(Args)]
+pub struct HooksArgs {
+    #[command(subcommand)]
+    command: HooksCommands,
+}
+
+#[derive(Subcommand)]
+enum HooksCommands {
+    /// Writes prepare-commit-msg and commit-msg hooks, which add the story to each commit made
+    /// on a branch with a story id, and refuse commits that drop it
+    Install {
+        /// How commits refer to the story
+        #[arg(long, value_enum, default_value = "id")]
+        mode: hooks::Mode,
+
+        /// Replace hooks that weren't installed by stories
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Removes the hooks installed by stories
+    Uninstall,
+
+    /// What the hooks run
+    #[command(hide = true)]
+    Run {
+        hook: String,
+
+        #[arg(long, value_enum)]
+        mode: hooks::Mode,
+
+        /// The arguments git gave the hook
+        args: Vec<String>,
+    },
+}
+
+fn hooks(hooks_args: &HooksArgs) -> anyhow::Result<()> {
+    match &hooks_args.command {
+        HooksCommands::Install { mode, force } => {
+            let hooks_dir = git::hooks_dir(&env::current_dir()?)?;
+            let exe = env::current_exe().context("couldn't tell where stories is installed")?;
+            for path in hooks::install(&hooks_dir, &exe, *mode, *force)? {
+                println!("installed {}", path.display());
+            }
+            Ok(())
+        }
+        HooksCommands::Uninstall => {
+            let hooks_dir = git::hooks_dir(&env::current_dir()?)?;
+            let removed = hooks::uninstall(&hooks_dir)?;
+            if removed.is_empty() {
+                println!("no hooks from stories to remove");
+            }
+            for path in removed {
+                println!("removed {}", path.display());
+            }
+            Ok(())
+        }
+        HooksCommands::Run { hook, mode, args } => {
+            // commits on branches without a story are none of our business
+            let Ok(story_id) = read_branch_id() else {
+                return Ok(());
+            };
+
+            let message_file = args
+                .first()
+                .ok_or_else(|| anyhow!("{} wasn't given a commit message file", hook))?;
+            let message = fs::read_to_string(message_file)
+                .with_context(|| format!("failed to read {}", message_file))?;
+
+            let reference = mode.reference(story_id);
+            match hook.as_str() {
+                "prepare-commit-msg" => {
+                    // merges, squashes and amends come with a message of their own
+                    let source = args.get(1).map(String::as_str);
+                    if matches!(source, Some("merge" | "squash" | "commit"))
+                        || hooks::mentions(&message, story_id)
+                    {
+                        return Ok(());
+                    }
+
+                    fs::write(message_file, hooks::stamp(&message, &reference))
+                        .with_context(|| format!("failed to write {}", message_file))?;
+                    Ok(())
+                }
+                // what prepare-commit-msg added doesn't count, so git still aborts a message
+                // that was left empty
+                "commit-msg" if hooks::is_empty(&message, &reference) => {
+                    Err(anyhow!("aborting commit due to empty commit message"))
+                }
+                "commit-msg" if hooks::mentions(&message, story_id) => Ok(()),
+                "commit-msg" => Err(anyhow!(
+                    "the commit message doesn't mention story #{}, add {} to it or commit with --no-verify",
+                    story_id,
+                    reference
+                )),
+                other => Err(anyhow!("{} isn't a hook stories knows", other)),
+            }
+        }
+    }
+}
+
+async fn auth(auth_args: &AuthArgs) -> anyhow::Result<()> {
+    let credentials = credentials()?;
+
+    match auth_args.command {
+        AuthCommands::Login { openai } => {
+            let service = if openai {
+                Service::OpenAi
+            } else {
+                Service::Tracker
+            };
+
+            if atty::is(atty::Stream::Stdin) {
+                eprint!("Paste your {} api token: ", service.name());
+                std::io::stderr().flush()?;
+            }
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+            let token = input.trim();
+            if token.is_empty() {
+                return Err(anyhow!("no token provided"));
+            }
+
+            if service == Service::Tracker {
+                let client = tracker_client(token)?;
+                let me = client
+                    .me()
+                    .await
+                    .context("tracker didn't accept that token")?;
+                println!("logged in to tracker as {} ({})", me.name, me.username);
+            }
+
+            let source = credentials.save(service, token)?;
+            println!("saved the {} token to {}", service.name(), source);
+        }
+        AuthCommands::Logout { openai } => {
+            let service = if openai {
+                Service::OpenAi
+            } else {
+                Service::Tracker
+            };
+
+            let credential = credentials.get(service)?;
+            if let Some(credentials::Source::Env(name)) = credential.as_ref().map(|c| &c.source) {
+                return Err(anyhow!(
+                    "the {} token comes from ${}, unset it to log out",
+                    service.name(),
+                    name
+                ));
+            }
+
+            if let (Service::Tracker, Some(credential)) = (service, &credential) {
+                tracker_client(&credential.secret)?
+                    .with_cache(Cache::new(cache_dir()?))
+                    .forget("me")
+                    .await;
+            }
+
+            match credentials.remove(service)? {
+                Some(source) => println!("logged out of {}, removed {}", service.name(), source),
+                None => println!("not logged in to {}", service.name()),
+            }
+        }
+        AuthCommands::Status => {
+            match credentials.get(Service::Tracker)? {
+                Some(credential) => {
+                    let client = tracker_client(&credential.secret)?;
+                    match client.me().await {
+                        Ok(me) => println!(
+                            "tracker: logged in as {} ({}), token from {}",
+                            me.name, me.username, credential.source
+                        ),
+                        Err(err) => println!(
+                            "tracker: the token from {} doesn't work: {}",
+                            credential.source, err
+                        ),
+                    }
+                }
+                None => println!("tracker: not logged in"),
+            }
+
+            match credentials.get(Service::OpenAi)? {
+                Some(credential) => println!("openai: key from {}", credential.source),
+                None => println!("openai: not logged in"),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn format_age(age: std::time::Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+#[derive(clap::ValueEnum, Clone)]
+enum ActivityFormat {
+    Plain,
+    Full,
+    /// Dated headings with linked stories, for pasting into chat or a standup doc
+    Markdown,
+    /// The report grouped by date then story, unlike --json which is tracker's raw feed
+    Json,
+}
+
+/// How much of a list to fetch, for commands that list things
+#[derive(Args)]
+pub struct LimitArgs {
+    /// Fetch at most this many
+    #[arg(long, default_value_t = 100)]
+    limit: usize,
+
+    /// Fetch everything, however many pages that takes
+    #[arg(long, conflicts_with = "limit")]
+    all: bool,
+}
+
+impl LimitArgs {
+    fn limit(&self) -> Option<usize> {
+        (!self.all).then_some(self.limit)
+    }
+}
+
+#[derive(Args)]
+pub struct ActivityArgs {
+    // defaults to plain
+    #[arg(short, long, default_value = "full")]
+    format: ActivityFormat,
+
+    /// Print json response
+    #[arg(short, long)]
+    json: bool,
+
+    /// Only activity on or after this date, e.g. 2023-07-01
+    #[arg(long, value_name = "DATE")]
+    since: Option<NaiveDate>,
+
+    /// Only activity on or before this date
+    #[arg(long, value_name = "DATE")]
+    until: Option<NaiveDate>,
+
+    /// Only the last N days of activity, including today
+    #[arg(long, value_name = "N", conflicts_with = "since")]
+    days: Option<u32>,
+
+    /// Whose activity: initials, a username or name, `me`, or `all` for the whole team
+    #[arg(long, value_name = "PERSON", default_value = "me")]
+    person: String,
+
+    /// Write a yesterday / today / blockers standup note from the activity with chatgpt,
+    /// covering the last 2 days unless told otherwise
+    #[arg(short, long)]
+    summarize: bool,
+
+    /// The model to summarize with, instead of the ai_model setting
+    #[arg(long, requires = "summarize")]
+    model: Option<String>,
+
+    #[command(flatten)]
+    limit: LimitArgs,
+}
+
+impl ActivityArgs {
+    /// `occurred_after` and `occurred_before` query params for the chosen dates
+    fn range(&self) -> anyhow::Result<Vec<(&'static str, String)>> {
+        let days = self.days.or_else(|| {
+            (self.summarize && self.since.is_none() && self.until.is_none()).then_some(2)
+        });
+        let since = match days {
+            Some(0) => return Err(anyhow!("--days needs to be at least 1")),
+            Some(days) => Some(
+                Local::now()
+                    .date_naive()
+                    .checked_sub_days(chrono::Days::new(days as u64 - 1))
+                    .ok_or_else(|| anyhow!("--days {} goes back further than dates go", days))?,
+            ),
+            None => self.since,
+        };
+
+        if let (Some(since), Some(until)) = (since, self.until) {
+            if until < since {
+                return Err(anyhow!("--until is before the start of the report"));
+            }
+        }
+
+        let mut range = vec![];
+        if let Some(since) = since {
+            range.push(("occurred_after", start_of_day(since).to_rfc3339()));
+        }
+        if let Some(until) = self.until {
+            let next_day = until + chrono::Days::new(1);
+            range.push(("occurred_before", start_of_day(next_day).to_rfc3339()));
+        }
+        Ok(range)
+    }
+}
+
+const STANDUP_PROMPT: &str = indoc! {"
+    You write short standup notes from a list of changes to stories, oldest first. Reply with
+    three markdown sections, Yesterday, Today and Blockers, each a few brief bullet points.
+    Today is whatever was started and isn't finished yet, and rejected stories are likely
+    blockers. Only mention work that's in the list, and say \"none\" when there's nothing to say.
+"};
+
+async fn activity(activity_args: &ActivityArgs) -> anyhow::Result<()> {
+    let client = tracker_api_client()?;
+    let projects = read_projects()?;
+    let project_ids: Vec<u64> = projects.iter().map(|p| p.id).collect();
+
+    let range = activity_args.range()?;
+    let query: Vec<(&str, &str)> = range
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect();
+
+    // your own activity comes from one feed across projects, anyone else's from each project's
+    // feed, narrowed down to what they did
+    let person = activity_args.person.as_str();
+    let mut feeds: Vec<(String, Option<u64>)> = vec![];
+    if person == "me" {
+        feeds.push(("my/activity".to_string(), None));
+    } else {
+        let mut not_found = None;
+        for project in &projects {
+            let performer = if person == "all" {
+                None
+            } else {
+                match resolve_owner_ids(&client, project.id, &[person.to_string()]).await {
+                    Ok(ids) => ids.first().copied(),
+                    Err(e) => {
+                        not_found.get_or_insert(e);
+                        continue;
+                    }
+                }
+            };
+            feeds.push((format!("projects/{}/activity", project.id), performer));
+        }
+        if feeds.is_empty() {
+            return Err(not_found.unwrap_or_else(|| anyhow!("no projects to read activity from")));
+        }
+    }
+
+    if wants_json(activity_args.json)? {
+        let mut activities: Vec<serde_json::Value> = vec![];
+        for (path, performer) in &feeds {
+            let pages = client.paginate::<serde_json::Value>(path, &query);
+            let feed = match performer {
+                Some(id) => {
+                    pages
+                        .collect_where(activity_args.limit.limit(), |a| {
+                            a["performed_by"]["id"].as_u64() == Some(*id)
+                        })
+                        .await?
+                }
+                None => pages.collect(activity_args.limit.limit()).await?,
+            };
+            activities.extend(feed);
+        }
+        // with several feeds, the limit keeps the newest across all of them
+        activities.sort_by(|a, b| b["occurred_at"].as_str().cmp(&a["occurred_at"].as_str()));
+        activities.truncate(activity_args.limit.limit().unwrap_or(usize::MAX));
+        println!("{}", serde_json::to_string(&activities)?);
+        return Ok(());
+    }
+
+    let mut activities: Vec<Activity> = vec![];
+    for (path, performer) in &feeds {
+        // the limit counts what's kept, so filtering by performer can't come up short
+        let pages = client.paginate::<Activity>(path, &query);
+        let feed = match performer {
+            Some(id) => {
+                pages
+                    .collect_where(activity_args.limit.limit(), |a| {
+                        a.performed_by.as_ref().map(|p| p.id) == Some(*id)
+                    })
+                    .await?
+            }
+            None => pages.collect(activity_args.limit.limit()).await?,
+        };
+        activities.extend(feed);
+    }
+    activities.sort_by(|a, b| b.occurred_at.cmp(&a.occurred_at));
+    activities.truncate(activity_args.limit.limit().unwrap_or(usize::MAX));
+    let days = report::group(activities, &project_ids);
+    let show_people = person == "all";
+
+    if activity_args.summarize {
+        if days.is_empty() {
+            return Err(anyhow!("there's no activity to summarize"));
+        }
+
+        let mut descriptions = HashMap::new();
+        let story_ids = days
+            .iter()
+            .flat_map(|day| &day.stories)
+            .filter_map(|story| story.id)
+            .unique();
+        // descriptions are extra context, so stories that were deleted or can't be seen are
+        // summarized without one
+        for story_id in story_ids {
+            if let Ok(story) = client.find_story(story_id).await {
+                if let Some(description) = story.description {
+                    descriptions.insert(story_id, description);
+                }
+            }
+        }
+
+        let config = read_config()?;
+        let note = summarizer(activity_args.model.as_deref())?
+            .summarize(Request {
+                instructions: config.standup_prompt().unwrap_or(STANDUP_PROMPT),
+                prompt: report::prompt(&days, &descriptions),
+                max_tokens: 400,
+            })
+            .await?;
+        println!("{}", note);
+        return Ok(());
+    }
+
+    match activity_args.format {
+        ActivityFormat::Plain => print!("{}", report::text(&days, false, show_people)),
+        ActivityFormat::Full => print!("{}", report::text(&days, true, show_people)),
+        ActivityFormat::Markdown => print!("{}", report::markdown(&days, show_people)),
+        ActivityFormat::Json => println!("{}", serde_json::to_string(&days)?),
+    }
+
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct ViewArgs {
+    /// Optionally provide a story id, otherwise find it in the current git branch
+    story_id: Option<String>,
+
+    /// Open the story in a web browser
+    #[arg(short, long)]
+    web: bool,
+
+    /// Print json response
+    #[arg(short, long)]
+    json: bool,
+
+    /// Include the story's comments
+    #[arg(short, long)]
+    comments: bool,
+}
+
+pub async fn view(view_args: &ViewArgs) -> anyhow::Result<()> {
+    let branch_id = match &view_args.story_id {
+        Some(id) => parse_story_id(id)?,
+        None => read_branch_id()?,
+    };
+
+    if view_args.web {
+        let url = format!("https://www.pivotaltracker.com/story/show/{}", branch_id);
+        webbrowser::open(&url)?;
+        println!("opened {}", url);
+        return Ok(());
+    }
+
+    let client = tracker_api_client()?;
+
+    let project_id = story_project_id(&client, branch_id).await?;
+
+    if wants_json(view_args.json)? {
+        let path = format!("projects/{}/stories/{}", project_id, branch_id);
+        println!("{}", client.get_raw(&path).await?);
+        return Ok(());
+    }
+
+    let sd = client.get_story(project_id, branch_id).await?;
+    // tasks are cached along with the story, but a story only fetched with --json doesn't
+    // have them, so offline it's shown without
+    let tasks = match client.list_tasks(project_id, branch_id).await {
+        Err(TrackerError::Offline { .. }) => vec![],
+        result => result?,
+    };
+    let comments = if view_args.comments {
+        client.list_comments(project_id, branch_id).await?
+    } else {
+        vec![]
+    };
+
+    let view_on_web = format!("View this story on Tracker: {}", sd.url);
+    println!(
+        "{}: {}\n{}\n",
+        format_story_type(&sd.story_type),
+        sd.name.black().bold(),
+        format_current_state(&sd.current_state),
+    );
+    let line = "────────────────────────────────────────────────────────────────────────────────";
+
+    let description = &sd
+        .description
+        .unwrap_or("(description missing)".to_string());
+
+    println!("{}", line.truecolor(100, 100, 100));
+
+    let max_width = if atty::is(atty::Stream::Stdout) {
+        Some(80)
+    } else {
+        None
+    };
+
+    print_markdown(description, max_width)?;
+
+    let links = extract_links(description);
+
+    if !links.is_empty() {
+        let link_string = links.iter().map(|link| format!("- {}", link)).join("\n");
+        let link_doc = format!("## Links\n{}", link_string);
+        println!();
+        print_markdown(&link_doc, None)?;
+    }
+
+    if !tasks.is_empty() {
+        println!();
+        print_markdown(&format!("## Tasks\n{}", format_tasks(&tasks)), max_width)?;
+    }
+
+    for comment in &comments {
+        println!("\n{}\n", line.truecolor(100, 100, 100));
+        print_comment(comment, max_width)?;
+    }
+
+    println!("\n{}\n", line.truecolor(100, 100, 100));
+    println!("{}", view_on_web.truecolor(200, 200, 200));
+
+    Ok(())
+}
+
+fn print_comment(comment: &api::schema::Comment, max_width: Option<u16>) -> anyhow::Result<()> {
+    let author = comment
+        .person
+        .as_ref()
+        .map(|person| person.name.clone())
+        .unwrap_or_else(|| format!("person {}", comment.person_id));
+
+    let timestamp = DateTime::parse_from_rfc3339(&comment.created_at)
+        .map(|datetime| {
+            datetime
+                .with_timezone(&Local)
+                .format("%a %b %d %Y %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|_| comment.created_at.clone());
+
+    println!(
+        "{} · {} {}\n",
+        author.bold(),
+        timestamp.italic(),
+        format!("#{}", comment.id).truecolor(100, 100, 100)
+    );
+
+    print_markdown(comment.text.as_deref().unwrap_or_default(), max_width)
+}
+
+#[derive(Args)]
+pub struct CommentArgs {
+    /// Optionally provide a story id, otherwise find it in the current git branch
+    story_id: Option<String>,
+
+    /// The comment, otherwise it's read from stdin or written in your $EDITOR
+    #[arg(short, long)]
+    message: Option<String>,
+
+    /// Reply to a comment, quoting it and mentioning its author
+    #[arg(short, long)]
+    reply_to: Option<u64>,
+}
+
+pub async fn comment(comment_args: &CommentArgs) -> anyhow::Result<()> {
+    let story_id = match &comment_args.story_id {
+        Some(id) => parse_story_id(id)?,
+        None => read_branch_id()?,
+    };
+
+    let client = tracker_api_client()?;
+    let project_id = story_project_id(&client, story_id).await?;
+
+    let quoted = match comment_args.reply_to {
+        Some(comment_id) => {
+            let original = client.get_comment(project_id, story_id, comment_id).await?;
+            Some(reply_preamble(&original))
+        }
+        None => None,
+    };
+
+    let text = match &comment_args.message {
+        Some(message) => message.clone(),
+        None if !atty::is(atty::Stream::Stdin) => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input)?;
+            input
+        }
+        None => editor::edit(quoted.as_deref().unwrap_or_default())?,
+    };
+
+    let text = match quoted {
+        Some(quoted) if !text.starts_with(&quoted) => format!("{}{}", quoted, text),
+        _ => text,
+    };
+
+    if text.trim().is_empty() {
+        return Err(anyhow!("the comment is empty, nothing was posted"));
+    }
+
+    let posted = client
+        .create_comment(project_id, story_id, text.trim())
+        .await?;
+
+    println!(
+        "Commented on #{} ({})",
+        story_id,
+        format!("#{}", posted.id).italic()
+    );
+
+    Ok(())
+}
+
+/// Quotes a comment and mentions its author, tracker has no threads so this is how replies look
+fn reply_preamble(comment: &api::schema::Comment) -> String {
+    let quote = comment
+        .text
+        .as_deref()
+        .unwrap_or_default()
+        .lines()
+        .map(|line| format!("> {}", line).trim_end().to_string())
+        .join("\n");
+
+    match &comment.person {
+        Some(person) => format!("@{} wrote:\n{}\n\n", person.username, quote),
+        None => format!("{}\n\n", quote),
+    }
+}
+
+#[derive(Args)]
+pub struct TaskArgs {
+    #[command(subcommand)]
+    command: Option<TaskCommands>,
+
+    /// Optionally provide a story id, otherwise find it in the current git branch
+    #[arg(short, long, global = true)]
+    story: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum TaskCommands {
+    /// Lists the story's tasks, this is the default
+    #[clap(alias = "ls")]
+    List,
+
+    /// Adds a task to the end of the list
+    Add { description: String },
+
+    /// Checks off a task, by its number in the list
+    Done { number: u32 },
+
+    /// Unchecks a task, by its number in the list
+    Undo { number: u32 },
+
+    /// Deletes a task, by its number in the list
+    Rm { number: u32 },
+}
+
+pub async fn task(task_args: &TaskArgs) -> anyhow::Result<()> {
+    let story_id = match &task_args.story {
+        Some(id) => parse_story_id(id)?,
+        None => read_branch_id()?,
+    };
+
+    let client = tracker_api_client()?;
+    let project_id = story_project_id(&client, story_id).await?;
+
+    let tasks = client.list_tasks(project_id, story_id).await?;
+
+    let find_task = |number: u32| {
+        tasks
+            .iter()
+            .find(|task| task.position == number)
+            .ok_or_else(|| anyhow!("#{} doesn't have a task {}", story_id, number))
+    };
+
+    match task_args.command.as_ref().unwrap_or(&TaskCommands::List) {
+        TaskCommands::List => {
+            if tasks.is_empty() {
+                println!("#{} doesn't have any tasks", story_id);
+            } else {
+                println!("{}", format_tasks(&tasks));
+            }
+        }
+        TaskCommands::Add { description } => {
+            let task = client
+                .create_task(project_id, story_id, description)
+                .await?;
+            println!("{}", format_task(&task));
+        }
+        TaskCommands::Done { number } => {
+            let task = find_task(*number)?;
+            let task = client
+                .complete_task(project_id, story_id, task.id, true)
+                .await?;
+            println!("{}", format_task(&task));
+        }
+        TaskCommands::Undo { number } => {
+            let task = find_task(*number)?;
+            let task = client
+                .complete_task(project_id, story_id, task.id, false)
+                .await?;
+            println!("{}", format_task(&task));
+        }
+        TaskCommands::Rm { number } => {
+            let task = find_task(*number)?;
+            client.delete_task(project_id, story_id, task.id).await?;
+            println!("removed {}", task.description.italic());
+        }
+    }
+
+    Ok(())
+}
+
+/// Tasks as a numbered markdown checklist, the numbers are what `stories task done` expects
+fn format_tasks(tasks: &[api::schema::Task]) -> String {
+    tasks.iter().map(format_task).join("\n")
+}
 
-    Ok(client)
+fn format_task(task: &api::schema::Task) -> String {
+    let check = if task.complete { "x" } else { " " };
+    format!("{}. [{}] {}", task.position, check, task.description)
 }
 
 #[derive(Args)]
-pub struct BranchArgs {
-    story_id: String,
-
-    /// Optionally provide a different branch name prefix, defaults to story name
+pub struct ProjectArgs {
+    /// Open the project in a web browser
     #[arg(short, long)]
-    name: Option<String>,
+    web: bool,
 
+    /// Print json response
     #[arg(short, long)]
-    estimate: Option<u8>,
+    json: bool,
 }
 
-pub async fn branch(branch_args: &BranchArgs) -> anyhow::Result<()> {
-    let client = tracker_api_client().await?;
+pub async fn project(project_args: &ProjectArgs) -> anyhow::Result<()> {
     let project_id = read_project_id()?;
-    let story_id = parse_story_id(&branch_args.story_id)?;
-    let me = tracker_me().await?;
 
-    let story_url = format!(
-        "https://www.pivotaltracker.com/services/v5/projects/{}/stories/{}",
-        project_id, story_id
-    );
+    if project_args.web {
+        let web_url = format!("https://www.pivotaltracker.com/n/projects/{}", project_id);
 
-    let data: api::schema::StoryDetail = client.get(&story_url).send().await?.json().await?;
-    // let me = tracker_me().await?;
+        webbrowser::open(&web_url)?;
 
-    // let story_type = match &data.story_type {
-    //     StoryType::Bug => "bug",
-    //     StoryType::Feature => "feat",
-    //     StoryType::Chore => "chore",
-    //     StoryType::Release => "release",
-    // };
+        println!("opened {}", web_url);
+        return Ok(());
+    }
 
-    let name_formatted = match &branch_args.name {
-        Some(val) => format!("-{}", val),
-        None => data.name,
-    };
+    let client = tracker_api_client()?;
 
-    let branch_name = format!("{}-{}", slugify!(&name_formatted, max_length = 40), data.id);
+    if wants_json(project_args.json)? {
+        let path = format!("projects/{}", project_id);
+        println!("{}", client.get_raw(&path).await?);
+        return Ok(());
+    }
 
-    let mut map = Map::new();
-    map.insert(
-        "current_state".to_string(),
-        Value::String("started".to_string()),
-    );
+    let project = client.get_project(project_id).await?;
+    let iteration = client.current_iteration(project_id).await?;
 
-    map.insert(
-        "owner_ids".to_string(),
-        Value::Array(vec![Value::Number(Number::from(me.id))]),
+    println!("{} #{}", project.name.bold(), project.id);
+    println!(
+        "Velocity: {}",
+        project
+            .current_velocity
+            .map(|velocity| velocity.to_string())
+            .unwrap_or_else(|| "-".to_string())
     );
 
-    if branch_args.estimate.is_some() {
-        map.insert(
-            "estimate".to_string(),
-            Value::Number(Number::from(branch_args.estimate.unwrap())),
-        );
-    }
+    let iteration = match iteration {
+        Some(iteration) => iteration,
+        None => {
+            println!("\nthere's no current iteration");
+            return Ok(());
+        }
+    };
 
-    let response = client.put(&story_url).json(&map).send().await?;
+    println!(
+        "Iteration {}: {} – {}\n",
+        iteration.number,
+        format_iteration_date(&iteration.start)?,
+        format_iteration_date(&iteration.finish)?
+    );
 
-    let status = response.status();
-    let response_text = response.text().await?;
+    if iteration.stories.is_empty() {
+        println!("the iteration has no stories");
+        return Ok(());
+    }
 
-    if !status.is_success() {
-        let error_data = serde_json::from_str::<api::schema::ApiError>(&response_text)?;
-        match &error_data.general_problem {
-            Some(problem) => {
-                return Err(anyhow!(format!("{}\n\n{}", status, problem)));
-            }
-            None => return Err(anyhow!(format!("{}\n\n{}", status, response_text))),
-        };
+    let points = |stories: &[&api::schema::Story]| -> u32 {
+        stories.iter().filter_map(|story| story.estimate).sum()
     };
 
-    let data = serde_json::from_str::<api::schema::StoryDetail>(&response_text)?;
+    #[derive(Tabled)]
+    struct StateRow {
+        #[tabled(rename = "State")]
+        state: String,
+        #[tabled(rename = "Stories")]
+        stories: usize,
+        #[tabled(rename = "Points")]
+        points: u32,
+    }
 
-    let git_result = Command::new("git")
-        .arg("switch")
-        .arg("-c")
-        .arg(&branch_name)
-        .output();
+    let state_rows: Vec<StateRow> = [
+        StoryState::Unscheduled,
+        StoryState::Unstarted,
+        StoryState::Planned,
+        StoryState::Started,
+        StoryState::Finished,
+        StoryState::Delivered,
+        StoryState::Rejected,
+        StoryState::Accepted,
+    ]
+    .iter()
+    .filter_map(|state| {
+        let stories: Vec<_> = iteration
+            .stories
+            .iter()
+            .filter(|story| story.current_state == *state)
+            .collect();
+
+        (!stories.is_empty()).then(|| StateRow {
+            state: format_current_state(state),
+            stories: stories.len(),
+            points: points(&stories),
+        })
+    })
+    .collect();
+
+    #[derive(Tabled)]
+    struct TypeRow {
+        #[tabled(rename = "Type")]
+        story_type: String,
+        #[tabled(rename = "Stories")]
+        stories: usize,
+        #[tabled(rename = "Points")]
+        points: u32,
+    }
 
-    let git_message = match git_result {
-        Ok(result) => {
-            if result.status.success() {
-                format!("checked out {}", branch_name.bold())
-            } else {
-                format!("🔥 failed! retry with:\n\tgit switch -c {}", branch_name)
-                    .red()
-                    .to_string()
-            }
-        }
-        Err(err) => {
-            // this right?
-            return Err(err.into());
-        }
-    };
+    let type_rows: Vec<TypeRow> = [
+        StoryType::Feature,
+        StoryType::Bug,
+        StoryType::Chore,
+        StoryType::Release,
+    ]
+    .iter()
+    .filter_map(|story_type| {
+        let stories: Vec<_> = iteration
+            .stories
+            .iter()
+            .filter(|story| story.story_type == *story_type)
+            .collect();
+
+        (!stories.is_empty()).then(|| TypeRow {
+            story_type: format_story_type(story_type).to_string(),
+            stories: stories.len(),
+            points: points(&stories),
+        })
+    })
+    .collect();
 
-    println!("Updated story: #{} {}", data.id, data.name.italic());
-    println!("Git branch: {}\n", git_message,);
+    let style = Style::modern()
+        .off_vertical()
+        .verticals([VerticalLine::new(1, Style::modern().get_vertical())]);
+
+    println!("{}\n", Table::new(&state_rows).with(style.clone()));
+    println!("{}", Table::new(&type_rows).with(style));
 
     Ok(())
 }
 
-async fn tracker_me() -> anyhow::Result<api::schema::Me> {
-    let token = read_api_token()?;
-    let client = tracker_api_client().await?;
-
-    let dir = Path::new(&config_dir()?).join("cache");
-    let cache_key = format!("tracker::me::{}", digest(&*token));
-
-    let cached = cacache::read(&dir, &cache_key).await;
-
-    match &cached {
-        Ok(cached) => Ok(serde_json::from_slice(cached)?),
-        Err(_) => {
-            let data: api::schema::Me = client
-                .get("https://www.pivotaltracker.com/services/v5/me")
-                .send()
-                .await?
-                .json()
-                .await?;
+/// Midnight at the start of `date`, in the local time zone
+fn start_of_day(date: NaiveDate) -> DateTime<Local> {
+    let midnight = date.and_time(NaiveTime::MIN);
+    midnight
+        .and_local_timezone(Local)
+        .earliest()
+        .unwrap_or_else(|| midnight.and_utc().with_timezone(&Local))
+}
 
-            let bytes = serde_json::to_vec(&data)?;
+fn format_iteration_date(timestamp: &str) -> anyhow::Result<String> {
+    let datetime = DateTime::parse_from_rfc3339(timestamp)
+        .with_context(|| format!("{:?} isn't a valid date", timestamp))?;
+    Ok(datetime
+        .with_timezone(&Local)
+        .format("%a %b %d")
+        .to_string())
+}
 
-            cacache::write(&dir, &cache_key, bytes).await?;
+#[derive(Clone, Debug, PartialEq)]
+enum IterationScope {
+    Current,
+    Backlog,
+    Icebox,
+    Number(u32),
+}
 
-            Ok(data)
+impl FromStr for IterationScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "current" => Ok(IterationScope::Current),
+            "backlog" => Ok(IterationScope::Backlog),
+            "icebox" => Ok(IterationScope::Icebox),
+            number => number.parse().map(IterationScope::Number).map_err(|_| {
+                format!(
+                    "expected current, backlog, icebox or an iteration number, not {:?}",
+                    number
+                )
+            }),
         }
     }
 }
 
-#[derive(clap::ValueEnum, Clone)]
-enum ActivityFormat {
-    Plain,
-    Full,
-}
-
 #[derive(Args)]
-pub struct ActivityArgs {
-    // defaults to plain
-    #[arg(short, long, default_value = "full")]
-    format: ActivityFormat,
+pub struct IterationArgs {
+    /// current, backlog, icebox or an iteration number
+    #[arg(default_value = "current")]
+    scope: IterationScope,
 
     /// Print json response
     #[arg(short, long)]
     json: bool,
 }
 
-async fn activity(activity_args: &ActivityArgs) -> anyhow::Result<()> {
-    #[derive(Tabled, Debug)]
-    struct ActivityRow {
-        #[tabled(rename = "Story")]
-        name: String,
-        #[tabled(rename = "Changes")]
-        highlights: String,
-    }
-
-    let client = tracker_api_client().await?;
+pub async fn iteration(iteration_args: &IterationArgs) -> anyhow::Result<()> {
+    let client = tracker_api_client()?;
     let project_id = read_project_id()?;
 
-    if activity_args.json {
-        let resp = client
-            .get("https://www.pivotaltracker.com/services/v5/my/activity")
-            .send()
-            .await?
-            .text()
-            .await?;
-
-        println!("{}", resp);
+    if wants_json(iteration_args.json)? {
+        let path = match iteration_args.scope {
+            IterationScope::Current => format!("projects/{}/iterations?scope=current", project_id),
+            IterationScope::Backlog => format!("projects/{}/iterations?scope=backlog", project_id),
+            IterationScope::Icebox => {
+                // the icebox can be long, so it's paged through like the text version
+                let stories: Vec<serde_json::Value> = client
+                    .paginate(
+                        &format!("projects/{}/stories", project_id),
+                        &[("filter", "state:unscheduled")],
+                    )
+                    .collect(None)
+                    .await?;
+                println!("{}", serde_json::to_string(&stories)?);
+                return Ok(());
+            }
+            IterationScope::Number(number) => {
+                format!("projects/{}/iterations/{}", project_id, number)
+            }
+        };
+        println!("{}", client.get_raw(&path).await?);
         return Ok(());
     }
 
-    let activities: Vec<api::schema::Activity> = client
-        .get("https://www.pivotaltracker.com/services/v5/my/activity")
-        .send()
+    let initials: HashMap<u64, String> = client
+        .project_memberships(project_id)
         .await?
-        .json()
-        .await?;
-
-    activities
-        .into_iter()
-        .rev()
-        .filter(|a| a.project.id == project_id)
-        .filter(|a| a.kind == "story_update_activity")
-        .filter(|a| {
-            matches!(
-                a.highlight.as_str(),
-                "delivered" | "started" | "finished" | "rejected" | "accepted"
-            )
-        })
-        .group_by(|a| {
-            let datetime_utc = DateTime::parse_from_rfc3339(&a.occurred_at).unwrap();
-            datetime_utc.with_timezone(&Local).date_naive()
-        })
         .into_iter()
-        .for_each(|(date, activities_by_date)| {
-            println!("{}\n----------\n", date.format("%a %b %d"));
-            activities_by_date
-                .sorted_by(|a, b| {
-                    a.primary_resources[0]
-                        .id
-                        .partial_cmp(&b.primary_resources[0].id)
-                        .unwrap()
-                })
-                .group_by(|a| {
-                    a.primary_resources[0]
-                        .name
-                        .as_ref()
-                        .unwrap_or(&a.primary_resources[0].kind)
-                        .to_string()
-                })
-                .into_iter()
-                .for_each(|(story_label, activities_for_story)| {
-                    let highlights = activities_for_story
-                        .sorted_by(|a, b| a.occurred_at.partial_cmp(&b.occurred_at).unwrap())
-                        .map(|a| match a.highlight.as_str() {
-                            "delivered" => "delivered".green().to_string(),
-                            "finished" => "finished".cyan().to_string(),
-                            other => other.to_string(),
-                        })
-                        .collect::<Vec<String>>()
-                        .join(", ");
-
-                    println!("{}", story_label.trim());
-                    match activity_args.format {
-                        ActivityFormat::Plain => {
-                            // noop
-                        }
-                        ActivityFormat::Full => {
-                            println!("  └── {}", highlights.italic());
-                        }
-                    }
-                });
-
-            println!();
-        });
-
-    Ok(())
-}
-
-#[derive(Args)]
-pub struct ViewArgs {
-    /// Optionally provide a story id, otherwise find it in the current git branch
-    story_id: Option<String>,
-
-    /// Open the story in a web browser
-    #[arg(short, long)]
-    web: bool,
+        .map(|membership| (membership.person.id, membership.person.initials))
+        .collect();
 
-    /// Print json response
-    #[arg(short, long)]
-    json: bool,
-}
+    let iterations = match iteration_args.scope {
+        IterationScope::Current => client
+            .current_iteration(project_id)
+            .await?
+            .into_iter()
+            .collect(),
+        IterationScope::Backlog => client.list_iterations(project_id, "backlog").await?,
+        IterationScope::Number(number) => match client.get_iteration(project_id, number).await {
+            Err(TrackerError::Api { error, .. }) if error.code == "unfound_resource" => {
+                return Err(anyhow!("there's no iteration {}", number));
+            }
+            result => vec![result?],
+        },
+        IterationScope::Icebox => {
+            let stories = client
+                .search_stories(project_id, "state:unscheduled")
+                .collect(None)
+                .await?;
+            let points: u32 = stories.iter().filter_map(|story| story.estimate).sum();
 
-pub async fn view(view_args: &ViewArgs) -> anyhow::Result<()> {
-    let branch_id = match &view_args.story_id {
-        Some(id) => parse_story_id(id)?,
-        None => read_branch_id()?,
+            println!("{}", "Icebox".bold());
+            println!("{} stories, {} points\n", stories.len(), points);
+            print_iteration_stories(&stories, &initials);
+            return Ok(());
+        }
     };
 
-    if view_args.web {
-        let url = format!("https://www.pivotaltracker.com/story/show/{}", branch_id);
-        webbrowser::open(&url)?;
-        println!("opened {}", url);
-        return Ok(());
+    if iterations.is_empty() {
+        match iteration_args.scope {
+            IterationScope::Backlog => println!("the backlog is empty"),
+            _ => println!("there's no current iteration"),
+        }
     }
 
-    let client = tracker_api_client().await?;
-
-    let project_id = read_project_id()?;
-
-    let url = format!(
-        "https://www.pivotaltracker.com/services/v5/projects/{}/stories/{}",
-        project_id, branch_id
-    );
+    for iteration in iterations {
+        let total: u32 = iteration.stories.iter().filter_map(|s| s.estimate).sum();
+        let accepted: u32 = iteration
+            .stories
+            .iter()
+            .filter(|story| story.current_state == StoryState::Accepted)
+            .filter_map(|story| story.estimate)
+            .sum();
+
+        println!(
+            "{} · {} – {}",
+            format!("Iteration {}", iteration.number).bold(),
+            format_iteration_date(&iteration.start)?,
+            format_iteration_date(&iteration.finish)?
+        );
+        println!(
+            "{} {}/{} points accepted\n",
+            progress_bar(accepted, total, 20).green(),
+            accepted,
+            total
+        );
+        print_iteration_stories(&iteration.stories, &initials);
+        println!();
+    }
 
-    let response = client.get(url).send().await?;
+    Ok(())
+}
 
-    if view_args.json {
-        println!("{}", response.text().await?);
-        return Ok(());
+fn print_iteration_stories(stories: &[api::schema::Story], initials: &HashMap<u64, String>) {
+    if stories.is_empty() {
+        println!("no stories");
+        return;
     }
 
-    let data: api::schema::MaybeStoryDetail = response.json().await?;
-
-    match data {
-        api::schema::MaybeStoryDetail::StoryDetail(sd) => {
-            let view_on_web = format!("View this story on Tracker: {}", sd.url);
-            println!(
-                "{}: {}\n{}\n",
-                format_story_type(&sd.story_type),
-                sd.name.black().bold(),
-                format_current_state(&sd.current_state),
-            );
-            let line =
-                "────────────────────────────────────────────────────────────────────────────────";
+    #[derive(Tabled)]
+    struct IterationRow {
+        #[tabled(rename = "Id")]
+        id: u32,
+        #[tabled(rename = "⛬")]
+        story_type: &'static str,
+        #[tabled(rename = " ☑ ")]
+        current_state: ColoredString,
+        #[tabled(rename = "Pts")]
+        points: String,
+        #[tabled(rename = "Name")]
+        name: String,
+        #[tabled(rename = "Owners")]
+        owners: String,
+    }
 
-            let description = &sd
-                .description
-                .unwrap_or("(description missing)".to_string());
+    let rows: Vec<IterationRow> = stories
+        .iter()
+        .map(|story| IterationRow {
+            id: story.id,
+            story_type: story_type_icon(&story.story_type),
+            current_state: current_state_icon(&story.current_state),
+            points: story.estimate.map(|e| e.to_string()).unwrap_or_default(),
+            name: story.name.clone(),
+            owners: story
+                .owner_ids
+                .iter()
+                .map(|id| initials.get(id).cloned().unwrap_or_else(|| id.to_string()))
+                .join(", "),
+        })
+        .collect();
 
-            println!("{}", line.truecolor(100, 100, 100));
+    let style = Style::modern()
+        .off_vertical()
+        .verticals([VerticalLine::new(3, Style::modern().get_vertical())]);
 
-            let max_width = if atty::is(atty::Stream::Stdout) {
-                Some(80)
-            } else {
-                None
-            };
+    let mut table = Table::new(&rows);
+    table
+        .with(style)
+        .with(Modify::new(Rows::new(1..)).with(Width::wrap(60).keep_words()));
 
-            print_markdown(&description, max_width)?;
+    println!("{}", table);
+}
 
-            let links = extract_links(&description);
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Tracker search terms and filters, e.g. widget or "label:dashboard -state:accepted"
+    query: Vec<String>,
 
-            if !links.is_empty() {
-                let link_string = links.iter().map(|link| format!("- {}", link)).join("\n");
-                let link_doc = format!("## Links\n{}", link_string);
-                println!();
-                print_markdown(&link_doc, None)?;
-            }
+    /// Only stories with this label, can be repeated
+    #[arg(short, long)]
+    label: Vec<String>,
 
-            println!("\n{}\n", line.truecolor(100, 100, 100));
-            println!("{}", view_on_web.truecolor(200, 200, 200))
-        }
-        api::schema::MaybeStoryDetail::ApiError(why) => {
-            return Err(anyhow::anyhow!(format!(
-                "tracker api error: {}\n\n{}",
-                why.code, why.error
-            )))
-        }
-    }
+    /// Only stories in this state, can be repeated
+    #[arg(short, long)]
+    state: Vec<StoryState>,
 
-    Ok(())
-}
+    /// Only stories of this type, can be repeated
+    #[arg(short = 't', long = "type")]
+    story_type: Vec<StoryType>,
 
-#[derive(Args)]
-pub struct ProjectArgs {
-    /// Open the story in a web browser
+    /// Only stories owned by this person, by initials, username or "me"
     #[arg(short, long)]
-    web: bool,
+    owner: Option<String>,
 
     /// Print json response
     #[arg(short, long)]
     json: bool,
+
+    #[command(flatten)]
+    limit: LimitArgs,
 }
 
-pub async fn project(project_args: &ProjectArgs) -> anyhow::Result<()> {
+pub async fn search(search_args: &SearchArgs) -> anyhow::Result<()> {
+    let client = tracker_api_client()?;
     let project_id = read_project_id()?;
 
-    if project_args.web {
-        let web_url = format!("https://www.pivotaltracker.com/n/projects/{}", project_id);
+    let owner = match search_args.owner.as_deref() {
+        Some("me") => Some(tracker_me().await?.username),
+        owner => owner.map(str::to_string),
+    };
+    let filter = search_filter(search_args, owner.as_deref());
 
-        webbrowser::open(&web_url)?;
+    if filter.is_empty() {
+        return Err(anyhow!(
+            "give something to search for, e.g. `stories search widget --state started`"
+        ));
+    }
 
-        println!("opened {}", web_url);
+    if wants_json(search_args.json)? {
+        let stories: Vec<serde_json::Value> = client
+            .paginate(
+                &format!("projects/{}/stories", project_id),
+                &[("filter", &filter)],
+            )
+            .collect(search_args.limit.limit())
+            .await?;
+        println!("{}", serde_json::to_string(&stories)?);
+        return Ok(());
+    }
+
+    let stories = client
+        .search_stories(project_id, &filter)
+        .collect(search_args.limit.limit())
+        .await?;
+
+    if stories.is_empty() {
+        println!("no stories match {}", filter.italic());
         return Ok(());
     }
 
-    let client = tracker_api_client().await?;
+    print_stories(stories.into_iter().map(|story| (None, story)).collect());
+    Ok(())
+}
+
+/// Tracker's filter syntax for the query and flags, e.g. `widget label:"needs review" state:started`
+fn search_filter(search_args: &SearchArgs, owner: Option<&str>) -> String {
+    fn quoted(value: &str) -> String {
+        if value.contains(char::is_whitespace) {
+            format!("\"{}\"", value)
+        } else {
+            value.to_string()
+        }
+    }
+
+    let mut terms = search_args.query.clone();
 
-    let url = format!(
-        "https://www.pivotaltracker.com/services/v5/projects/{}",
-        project_id
+    terms.extend(
+        search_args
+            .label
+            .iter()
+            .map(|label| format!("label:{}", quoted(label))),
     );
 
-    let response = client.get(url).send().await?;
+    if !search_args.state.is_empty() {
+        terms.push(format!("state:{}", search_args.state.iter().join(",")));
+    }
 
-    if project_args.json {
-        println!("{}", response.text().await?);
-        return Ok(());
+    if !search_args.story_type.is_empty() {
+        terms.push(format!("type:{}", search_args.story_type.iter().join(",")));
+    }
+
+    if let Some(owner) = owner {
+        terms.push(format!("owner:{}", quoted(owner)));
     }
 
-    todo!("only --web and --json available");
+    terms.join(" ")
 }
 
 #[derive(Args)]
@@ -689,39 +2315,66 @@ pub struct MineArgs {
     /// Print json response
     #[arg(short, long)]
     json: bool,
+
+    #[command(flatten)]
+    limit: LimitArgs,
 }
 
 pub async fn mine(mine_args: &MineArgs) -> anyhow::Result<()> {
-    let client = tracker_api_client().await?;
-    let project_id = read_project_id()?;
+    let client = tracker_api_client()?;
+    let projects = read_projects()?;
     let me = tracker_me().await?;
 
-    // https://www.pivotaltracker.com/help/api/rest/v5#Stories
-    let url = format!(
-        "https://www.pivotaltracker.com/services/v5/projects/{}/stories?filter=mywork:{}",
-        project_id, me.id
-    );
-
-    let response = client.get(url).send().await?;
-
-    if mine_args.json {
-        println!("{}", response.text().await?);
+    let filter = format!("mywork:{}", me.id);
+    let limit = mine_args.limit.limit();
+
+    if wants_json(mine_args.json)? {
+        let mut stories: Vec<serde_json::Value> = vec![];
+        for project in &projects {
+            let path = format!("projects/{}/stories", project.id);
+            stories.extend(
+                client
+                    .paginate(&path, &[("filter", &filter)])
+                    .collect(limit)
+                    .await?,
+            );
+        }
+        stories.truncate(limit.unwrap_or(usize::MAX));
+        println!("{}", serde_json::to_string(&stories)?);
         return Ok(());
     }
 
-    let data: Vec<api::schema::Story> = response.json().await?;
+    // with several projects, each story's name is prefixed with the project it's in
+    let mut data = vec![];
+    for project in &projects {
+        let label = (projects.len() > 1).then(|| {
+            project
+                .alias
+                .clone()
+                .unwrap_or_else(|| project.id.to_string())
+        });
+        for story in client
+            .search_stories(project.id, &filter)
+            .collect(limit)
+            .await?
+        {
+            data.push((label.clone(), story));
+        }
+    }
+    data.truncate(limit.unwrap_or(usize::MAX));
+
+    print_stories(data);
+    Ok(())
+}
 
+/// Stories as a table, each optionally labelled with the project it's in
+fn print_stories(data: Vec<(Option<String>, api::schema::Story)>) {
     let rows: Vec<StoryRow> = data
         .into_iter()
-        .map(|entry| {
+        .map(|(project, entry)| {
             let link = Link::new("[↗]", &entry.url).to_string();
 
-            let story_type = match entry.story_type {
-                StoryType::Feature => "⭐️".to_string(),
-                StoryType::Bug => "🐞".to_string(),
-                StoryType::Chore => "🧹".to_string(),
-                StoryType::Release => "🏁".to_string(),
-            };
+            let story_type = story_type_icon(&entry.story_type).to_string();
 
             // todo clean this up, use something cool, e.g. ➊➁➍
             // https://en.wikipedia.org/wiki/List_of_Unicode_characters
@@ -745,6 +2398,11 @@ pub async fn mine(mine_args: &MineArgs) -> anyhow::Result<()> {
                 _ => format!("{} · {}{}", entry.name, labels, estimate),
             };
 
+            let name = match project {
+                Some(project) => format!("{} {}", project.dimmed(), name),
+                None => name,
+            };
+
             StoryRow {
                 id: entry.id.to_string(),
                 story_type,
@@ -780,67 +2438,103 @@ pub async fn mine(mine_args: &MineArgs) -> anyhow::Result<()> {
         .with(Modify::new(Rows::new(1..)).with(Width::wrap(name_wrap).keep_words()));
 
     println!("{}", table);
-    Ok(())
 }
 
 pub fn read_project_id() -> anyhow::Result<u64> {
-    let contents = fs::read_to_string("stories.json").with_context(|| {
-        indoc! {r#"
-            didn't find a stories.json in this directory.
+    let config = read_config()?;
+    match PROJECT_OVERRIDE.get() {
+        Some(project) => config.select_project(project),
+        None => config.project_id(),
+    }
+}
 
-            add one with something like this:
+/// The project picked with --project, otherwise every project in the config
+fn read_projects() -> anyhow::Result<Vec<ProjectRef>> {
+    let config = read_config()?;
+
+    if let Some(project) = PROJECT_OVERRIDE.get() {
+        let id = config.select_project(project)?;
+        let alias = config
+            .projects()
+            .into_iter()
+            .find(|p| p.id == id)
+            .and_then(|p| p.alias);
+        return Ok(vec![ProjectRef { alias, id }]);
+    }
 
-                $ echo '{"project_id":123456}' > stories.json
+    let projects = config.projects();
+    if projects.is_empty() {
+        // explains how to set up a stories.json
+        config.project_id()?;
+    }
+    Ok(projects)
+}
 
-            to find a project id, visit the project in the tracker website and look at the url
-            https://www.pivotaltracker.com/dashboard
-        "#}
-    })?;
+/// The project `story_id` belongs to. With several projects configured and none picked with
+/// --project, tracker is asked rather than guessing.
+async fn story_project_id(client: &TrackerClient, story_id: u64) -> anyhow::Result<u64> {
+    if PROJECT_OVERRIDE.get().is_some() || read_config()?.projects().len() <= 1 {
+        return read_project_id();
+    }
 
-    let config: ProjectConfig =
-        serde_json::from_str(&contents).context("stories.json isn't right")?;
-    Ok(config.project_id)
+    Ok(client.find_story(story_id).await?.project_id)
 }
 
-fn config_dir() -> anyhow::Result<PathBuf> {
-    let home = env::var("HOME").context("no $HOME env var defined? wacky")?;
-    let path = Path::new(&home).join(".config/stories");
-    Ok(path)
+fn read_config() -> anyhow::Result<Config> {
+    Config::load()
 }
 
-pub fn read_api_token() -> anyhow::Result<String> {
-    let path = Path::new(&config_dir()?).join("tracker_api_token.txt");
+/// --json, or output_format set to json in the config
+fn wants_json(flag: bool) -> anyhow::Result<bool> {
+    Ok(flag || read_config()?.output_format() == OutputFormat::Json)
+}
 
-    let path_string = path.as_os_str().to_str().unwrap();
+fn cache_dir() -> anyhow::Result<PathBuf> {
+    Ok(config_dir()?.join("cache"))
+}
 
-    let token_file_contents = fs::read_to_string(&path).context(format!(
-        indoc! {"
-            didn't find the credentials config file at {}
+pub fn read_api_token() -> anyhow::Result<String> {
+    if let Some(token) = API_TOKEN.get() {
+        return Ok(token.clone());
+    }
 
-            1. visit https://www.pivotaltracker.com/profile#api
-            2. note that token!
-            3. run something like this to dump it in
+    let token = credentials()?.read(Service::Tracker)?.secret;
+    Ok(API_TOKEN.get_or_init(|| token).clone())
+}
 
-                $ echo $YOUR_API_TOKEN > {}
-        "},
-        path_string, path_string
-    ))?;
+fn credentials() -> anyhow::Result<Credentials> {
+    let config = read_config()?;
+    if config.set_by_project("credential_helper") {
+        eprintln!(
+            "{}",
+            format!(
+                "ignoring the credential_helper in {}, set it in {} or $STORIES_CREDENTIAL_HELPER instead",
+                config.source("credential_helper"),
+                config.user_file.display()
+            )
+            .yellow()
+        );
+    }
 
-    Ok(token_file_contents.trim().to_string())
+    Ok(Credentials::new(
+        config_dir()?,
+        config.credential_helper().map(str::to_string),
+    ))
 }
 
 pub fn read_branch_id() -> anyhow::Result<u64> {
-    // possible enhancement: drag in a git crate to do this, and can likely
-    // a) get this from any dir in the git repo
-    // b) possibly avoid shelling out to git to create new branches
-
-    let head_contents = fs::read_to_string(".git/head")
-        .context("failed to read .git/head, are you in the root of a git repo?")?;
-
-    let branch =
-        branch_name(&head_contents).ok_or_else(|| anyhow!("no branch name found in .git/head"))?;
+    let branch = match BRANCH_OVERRIDE.get() {
+        Some(branch) => branch.clone(),
+        None => {
+            let repo = git::Repository::discover(&env::current_dir()?)?;
+            repo.current_branch()?.ok_or_else(|| {
+                anyhow!("HEAD is detached and no branch could be found for it, try --branch")
+            })?
+        }
+    };
 
-    let id = extract_id(&branch).ok_or_else(|| {
+    let template = branch_template(&read_config()?);
+    let id = branch_name::extract_id(&template, &branch).ok_or_else(|| {
         anyhow!(format!(
             indoc! {r#"
                 the current git branch doesn't appear to have an id in it.
@@ -864,24 +2558,25 @@ pub fn read_branch_id() -> anyhow::Result<u64> {
     Ok(id)
 }
 
-fn branch_name(head_contents: &str) -> Option<String> {
-    Some(
-        head_contents
-            .strip_prefix("ref: refs/heads/")?
-            .trim()
-            .to_string(),
-    )
+/// The branch template with the branch prefix in front, which is where story ids are in
+/// branch names
+fn branch_template(config: &Config) -> String {
+    format!("{}{}", config.branch_prefix(), config.branch_template())
 }
 
-fn extract_id(branch_name: &str) -> Option<u64> {
-    branch_name
-        .split(|c: char| !c.is_numeric())
-        .filter_map(|s| s.parse::<u64>().ok())
-        .last()
+fn conventional_type(story_type: &StoryType) -> &'static str {
+    match story_type {
+        StoryType::Bug => "fix",
+        StoryType::Feature => "feat",
+        StoryType::Chore => "chore",
+        StoryType::Release => "chore",
+    }
 }
 
+/// A story id typed on the command line, which can also be a story's url
 fn parse_story_id(s: &str) -> Result<u64> {
-    extract_id(s).ok_or_else(|| anyhow!("Could not parse story id from {}", s))
+    branch_name::extract_id(branch_name::DEFAULT_TEMPLATE, s)
+        .ok_or_else(|| anyhow!("Could not parse story id from {}", s))
 }
 
 fn print_markdown(text: &str, columns: Option<u16>) -> anyhow::Result<()> {
@@ -909,7 +2604,7 @@ fn print_markdown(text: &str, columns: Option<u16>) -> anyhow::Result<()> {
 
     let settings = Settings {
         terminal_capabilities: terminal.capabilities(),
-        terminal_size: terminal_size,
+        terminal_size,
         syntax_set: &SYNTAX_SET,
         theme: Theme::default(),
     };
@@ -940,6 +2635,25 @@ fn current_state_icon(state: &StoryState) -> ColoredString {
     }
 }
 
+fn story_type_icon(story_type: &StoryType) -> &'static str {
+    match story_type {
+        StoryType::Feature => "⭐️",
+        StoryType::Bug => "🐞",
+        StoryType::Chore => "🧹",
+        StoryType::Release => "🏁",
+    }
+}
+
+/// e.g. `█████░░░░░` for half done
+fn progress_bar(done: u32, total: u32, width: usize) -> String {
+    let filled = match total {
+        0 => 0,
+        _ => ((done as usize * width + total as usize / 2) / total as usize).min(width),
+    };
+
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+}
+
 fn format_current_state(state: &StoryState) -> String {
     let message = match state {
         StoryState::Planned => "Planned".black(),
@@ -970,8 +2684,7 @@ fn extract_links(text: &str) -> Vec<String> {
     }
 
     RE.captures_iter(text)
-        .map(|cap| cap.name("url").map(|url| url.as_str().to_string()))
-        .flatten()
+        .filter_map(|cap| cap.name("url").map(|url| url.as_str().to_string()))
         .collect()
 }
 
@@ -981,19 +2694,50 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_branch_name() {
+    fn test_search_filter() {
+        #[derive(Parser)]
+        struct Search {
+            #[command(flatten)]
+            args: SearchArgs,
+        }
+
+        let args = Search::parse_from([
+            "search",
+            "--label",
+            "needs review",
+            "--state",
+            "started",
+            "--state",
+            "finished",
+            "--type",
+            "bug",
+            "--",
+            "widget",
+            "-label:wontfix",
+        ]);
+
         assert_eq!(
-            branch_name("ref: refs/heads/main"),
-            Some("main".to_string())
+            search_filter(&args.args, Some("Dana Developer")),
+            r#"widget -label:wontfix label:"needs review" state:started,finished type:bug owner:"Dana Developer""#
         );
     }
 
     #[test]
-    fn test_extract_id() {
-        assert_eq!(extract_id("yep-123"), Some(123));
-        assert_eq!(extract_id("yep-24-123"), Some(123));
-        assert_eq!(extract_id("123-456-yep"), Some(456));
-        assert_eq!(extract_id("foobar"), None);
+    fn test_progress_bar() {
+        assert_eq!(progress_bar(0, 0, 4), "░░░░");
+        assert_eq!(progress_bar(3, 6, 4), "██░░");
+        assert_eq!(progress_bar(5, 5, 4), "████");
+        assert_eq!(progress_bar(1, 8, 4), "█░░░");
+    }
+
+    #[test]
+    fn test_format_age() {
+        use std::time::Duration;
+
+        assert_eq!(format_age(Duration::from_secs(5)), "5s");
+        assert_eq!(format_age(Duration::from_secs(90)), "1m");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_age(Duration::from_secs(86400 * 3)), "3d");
     }
 
     #[test]
@@ -0,0 +1,261 @@
+use chrono::{DateTime, Local, NaiveDate};
+use colored::*;
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::HashMap;
+use stories::api::schema::Activity;
+
+/// The highlights that move a story along, the only activity worth reporting
+const TRANSITIONS: [&str; 5] = ["started", "finished", "delivered", "accepted", "rejected"];
+
+/// A day of activity, with each story that moved and how
+#[derive(Serialize, Debug)]
+pub struct Day {
+    pub date: NaiveDate,
+    pub stories: Vec<StoryActivity>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StoryActivity {
+    pub id: Option<u64>,
+    pub name: String,
+    pub url: Option<String>,
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Transition {
+    pub state: String,
+    pub occurred_at: String,
+    pub performed_by: Option<Performer>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Performer {
+    pub name: String,
+    pub initials: String,
+}
+
+/// Groups story transitions in `project_ids` by local date, then by story, oldest first
+pub fn group(mut activities: Vec<Activity>, project_ids: &[u64]) -> Vec<Day> {
+    activities.sort_by(|a, b| a.occurred_at.cmp(&b.occurred_at));
+
+    activities
+        .into_iter()
+        .filter(|a| project_ids.contains(&a.project.id))
+        .filter(|a| a.kind == "story_update_activity" && !a.primary_resources.is_empty())
+        .filter(|a| TRANSITIONS.contains(&a.highlight.as_str()))
+        .group_by(|a| local_date(&a.occurred_at))
+        .into_iter()
+        .map(|(date, activities_by_date)| Day {
+            date,
+            stories: activities_by_date
+                .sorted_by_key(|a| a.primary_resources[0].id)
+                .group_by(|a| a.primary_resources[0].id)
+                .into_iter()
+                .map(|(_, activities_for_story)| {
+                    let activities_for_story: Vec<Activity> = activities_for_story
+                        .sorted_by(|a, b| a.occurred_at.cmp(&b.occurred_at))
+                        .collect();
+                    let story = &activities_for_story[0].primary_resources[0];
+
+                    StoryActivity {
+                        id: story.id,
+                        name: story
+                            .name
+                            .as_ref()
+                            .unwrap_or(&story.kind)
+                            .trim()
+                            .to_string(),
+                        url: story.url.clone(),
+                        transitions: activities_for_story
+                            .iter()
+                            .map(|a| Transition {
+                                state: a.highlight.clone(),
+                                occurred_at: a.occurred_at.clone(),
+                                performed_by: a.performed_by.as_ref().map(|p| Performer {
+                                    name: p.name.clone(),
+                                    initials: p.initials.clone(),
+                                }),
+                            })
+                            .collect(),
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn local_date(timestamp: &str) -> NaiveDate {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|datetime| datetime.with_timezone(&Local).date_naive())
+        .unwrap_or_default()
+}
+
+/// The colored report for a terminal, with each story's transitions underneath it if `full`
+pub fn text(days: &[Day], full: bool, show_people: bool) -> String {
+    let mut out = String::new();
+
+    for day in days {
+        out.push_str(&format!("{}\n----------\n\n", day.date.format("%a %b %d")));
+
+        for story in &day.stories {
+            out.push_str(&format!("{}\n", story.name));
+            if full {
+                let transitions = story
+                    .transitions
+                    .iter()
+                    .map(|t| {
+                        let state = match t.state.as_str() {
+                            "delivered" => "delivered".green().to_string(),
+                            "finished" => "finished".cyan().to_string(),
+                            other => other.to_string(),
+                        };
+                        with_initials(state, t, show_people)
+                    })
+                    .join(", ");
+                out.push_str(&format!("  └── {}\n", transitions.italic()));
+            }
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+/// The report as markdown, for pasting into chat or a standup doc
+pub fn markdown(days: &[Day], show_people: bool) -> String {
+    days.iter()
+        .map(|day| {
+            let stories = day
+                .stories
+                .iter()
+                .map(|story| {
+                    let name = story.name.replace('[', "\\[").replace(']', "\\]");
+                    let link = match &story.url {
+                        Some(url) => format!("[{}]({})", name, url),
+                        None => name,
+                    };
+                    let transitions = story
+                        .transitions
+                        .iter()
+                        .map(|t| with_initials(t.state.clone(), t, show_people))
+                        .join(" → ");
+                    format!("- {}: {}", link, transitions)
+                })
+                .join("\n");
+
+            format!("## {}\n\n{}\n", day.date.format("%a %b %d"), stories)
+        })
+        .join("\n")
+}
+
+/// The report as plain text for a model to summarize, with each story's description (when
+/// there is one in `descriptions`) to say what the work was about
+pub fn prompt(days: &[Day], descriptions: &HashMap<u64, String>) -> String {
+    days.iter()
+        .map(|day| {
+            let stories = day
+                .stories
+                .iter()
+                .map(|story| {
+                    let transitions = story
+                        .transitions
+                        .iter()
+                        .map(|t| match &t.performed_by {
+                            Some(performer) => format!("{} by {}", t.state, performer.name),
+                            None => t.state.clone(),
+                        })
+                        .join(", ");
+                    let description = story
+                        .id
+                        .and_then(|id| descriptions.get(&id))
+                        .map(|description| {
+                            let description: String = description
+                                .trim()
+                                .chars()
+                                .take(DESCRIPTION_LENGTH)
+                                .collect();
+                            format!("\n  {}", description.replace('\n', "\n  "))
+                        })
+                        .unwrap_or_default();
+                    format!("- {}: {}{}", story.name, transitions, description)
+                })
+                .join("\n");
+
+            format!("{}\n{}\n", day.date.format("%A %Y-%m-%d"), stories)
+        })
+        .join("\n")
+}
+
+/// How much of each story's description goes in a prompt, to keep it a reasonable size
+const DESCRIPTION_LENGTH: usize = 500;
+
+fn with_initials(state: String, transition: &Transition, show_people: bool) -> String {
+    match (&transition.performed_by, show_people) {
+        (Some(performer), true) => format!("{} ({})", state, performer.initials),
+        _ => state,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(story_id: u64, highlight: &str, occurred_at: &str, project_id: u64) -> Activity {
+        serde_json::from_value(serde_json::json!({
+            "kind": "story_update_activity",
+            "message": format!("Dana Developer {} this feature", highlight),
+            "highlight": highlight,
+            "primary_resources": [{
+                "kind": "story",
+                "id": story_id,
+                "name": format!("Story {}", story_id),
+                "url": format!("https://www.pivotaltracker.com/story/show/{}", story_id),
+            }],
+            "project": { "kind": "project", "id": project_id, "name": "Widget Factory" },
+            "performed_by": { "kind": "person", "id": 101, "name": "Dana Developer", "initials": "DD" },
+            "occurred_at": occurred_at,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_group() {
+        let days = group(
+            vec![
+                activity(2, "finished", "2023-07-13T12:00:00Z", 1),
+                activity(2, "started", "2023-07-12T12:30:00Z", 1),
+                activity(1, "accepted", "2023-07-12T12:20:00Z", 1),
+                activity(1, "edited", "2023-07-12T12:10:00Z", 1),
+                activity(2, "delivered", "2023-07-13T12:30:00Z", 1),
+                activity(3, "started", "2023-07-12T12:00:00Z", 2),
+            ],
+            &[1],
+        );
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date.to_string(), "2023-07-12");
+        let names: Vec<&str> = days[0].stories.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Story 1", "Story 2"]);
+
+        let states: Vec<&str> = days[1].stories[0]
+            .transitions
+            .iter()
+            .map(|t| t.state.as_str())
+            .collect();
+        assert_eq!(states, ["finished", "delivered"]);
+
+        assert_eq!(
+            markdown(&days[1..], true),
+            "## Thu Jul 13\n\n- [Story 2](https://www.pivotaltracker.com/story/show/2): finished (DD) → delivered (DD)\n"
+        );
+
+        let descriptions = HashMap::from([(2, "Make the widget\nshinier".to_string())]);
+        assert_eq!(
+            prompt(&days[1..], &descriptions),
+            "Thursday 2023-07-13\n- Story 2: finished by Dana Developer, delivered by Dana Developer\n  Make the widget\n  shinier\n"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::anyhow;
+use stories::api::schema::{LabelName, StoryDetail, StoryType, StoryUpdate};
+
+/// A story as an editable markdown document: front matter for the fields, a heading for the
+/// name, and everything after that is the description, e.g.
+///
+/// ```text
+/// ---
+/// type: feature
+/// estimate: 2
+/// labels: dashboard, widgets
+/// owners: me
+/// ---
+///
+/// # Add a widget to the dashboard
+///
+/// The dashboard needs a widget.
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct StoryDocument {
+    pub name: String,
+    pub story_type: StoryType,
+    pub estimate: Option<u32>,
+    pub labels: Vec<String>,
+    /// `None` leaves the owners line out of the document entirely
+    pub owners: Option<Vec<String>>,
+    pub description: String,
+}
+
+const TEMPLATE_HELP: &str = "\
+# type is one of feature, bug, chore or release
+# labels and owners are comma separated, owners are initials, usernames or \"me\"
+# the heading below is the story's name, everything after it is the description";
+
+impl StoryDocument {
+    /// An empty story for `stories new`
+    pub fn template() -> String {
+        let document = StoryDocument {
+            name: String::new(),
+            story_type: StoryType::Feature,
+            estimate: None,
+            labels: vec![],
+            owners: Some(vec!["me".to_string()]),
+            description: String::new(),
+        };
+
+        document
+            .render()
+            .replacen("---\n", &format!("---\n{}\n", TEMPLATE_HELP), 1)
+    }
+
+    pub fn render(&self) -> String {
+        let mut front_matter = vec!["---".to_string()];
+        front_matter.push(format!("type: {}", self.story_type));
+        front_matter.push(format!(
+            "estimate: {}",
+            self.estimate.map(|e| e.to_string()).unwrap_or_default()
+        ));
+        front_matter.push(format!("labels: {}", self.labels.join(", ")));
+        if let Some(owners) = &self.owners {
+            front_matter.push(format!("owners: {}", owners.join(", ")));
+        }
+        front_matter.push("---".to_string());
+
+        format!(
+            "{}\n\n# {}\n\n{}\n",
+            front_matter.join("\n"),
+            self.name,
+            self.description
+        )
+    }
+
+    /// The fields that differ from `original`, as an update that leaves everything else alone.
+    /// Owners are names rather than ids, so they're left to the caller to look up.
+    pub fn changes_from(&self, original: &StoryDocument) -> StoryUpdate {
+        fn changed<T: PartialEq + Clone>(new: &T, old: &T) -> Option<T> {
+            (new != old).then(|| new.clone())
+        }
+
+        StoryUpdate {
+            name: changed(&self.name, &original.name),
+            story_type: changed(&self.story_type, &original.story_type),
+            estimate: changed(&self.estimate, &original.estimate),
+            labels: changed(&self.labels, &original.labels)
+                .map(|labels| labels.into_iter().map(|name| LabelName { name }).collect()),
+            description: changed(&self.description, &original.description),
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut lines = text.trim_start().lines();
+
+        if lines.next().map(str::trim) != Some("---") {
+            return Err(anyhow!(
+                "expected the story to start with a --- front matter line"
+            ));
+        }
+
+        let mut story_type = StoryType::Feature;
+        let mut estimate = None;
+        let mut labels = vec![];
+        let mut owners = None;
+        let mut closed = false;
+
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line == "---" {
+                closed = true;
+                break;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once(':').ok_or_else(|| {
+                anyhow!("expected `key: value` in the front matter, got {:?}", line)
+            })?;
+            let value = value.trim();
+
+            match key.trim() {
+                "type" => story_type = value.parse().map_err(|e: String| anyhow!(e))?,
+                "estimate" if value.is_empty() => estimate = None,
+                "estimate" => {
+                    estimate = Some(
+                        value
+                            .parse()
+                            .map_err(|_| anyhow!("{:?} isn't a valid estimate", value))?,
+                    )
+                }
+                "labels" => labels = split_list(value),
+                "owners" => owners = Some(split_list(value)),
+                other => return Err(anyhow!("unknown front matter field {:?}", other)),
+            }
+        }
+
+        if !closed {
+            return Err(anyhow!("the front matter is missing its closing --- line"));
+        }
+
+        let mut body = lines.skip_while(|line| line.trim().is_empty());
+
+        let name = body
+            .next()
+            .and_then(|line| line.trim().strip_prefix('#'))
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| {
+                anyhow!("the story needs a name, as a heading like `# Add a widget` after the front matter")
+            })?;
+
+        let description = body.collect::<Vec<&str>>().join("\n").trim().to_string();
+
+        Ok(StoryDocument {
+            name,
+            story_type,
+            estimate,
+            labels,
+            owners,
+            description,
+        })
+    }
+}
+
+impl From<&StoryDetail> for StoryDocument {
+    fn from(story: &StoryDetail) -> Self {
+        StoryDocument {
+            name: story.name.clone(),
+            story_type: story.story_type,
+            estimate: story.estimate,
+            labels: story
+                .labels
+                .iter()
+                .map(|label| label.name.clone())
+                .collect(),
+            owners: None,
+            description: story
+                .description
+                .clone()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let document = StoryDocument::parse(
+            "---\ntype: bug\nestimate: 2\nlabels: a, b\nowners: me, DD\n---\n\n# Fix it\n\nIt's broken.\n\n- [ ] really\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            document,
+            StoryDocument {
+                name: "Fix it".to_string(),
+                story_type: StoryType::Bug,
+                estimate: Some(2),
+                labels: vec!["a".to_string(), "b".to_string()],
+                owners: Some(vec!["me".to_string(), "DD".to_string()]),
+                description: "It's broken.\n\n- [ ] really".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_template() {
+        let filled = StoryDocument::template().replace("\n# \n", "\n# A new thing\n");
+        let document = StoryDocument::parse(&filled).unwrap();
+
+        assert_eq!(document.name, "A new thing");
+        assert_eq!(document.story_type, StoryType::Feature);
+        assert_eq!(document.estimate, None);
+        assert_eq!(document.owners, Some(vec!["me".to_string()]));
+        assert_eq!(document.description, "");
+    }
+
+    #[test]
+    fn test_parse_requires_name() {
+        assert!(StoryDocument::parse(&StoryDocument::template()).is_err());
+        assert!(StoryDocument::parse("---\ntype: bug\n---\n\nno heading").is_err());
+        assert!(StoryDocument::parse("---\ntype: spike\n---\n\n# Name").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let document = StoryDocument {
+            name: "Round trip".to_string(),
+            story_type: StoryType::Chore,
+            estimate: None,
+            labels: vec!["x".to_string()],
+            owners: None,
+            description: "# a heading in the description\n\ntext".to_string(),
+        };
+
+        assert_eq!(StoryDocument::parse(&document.render()).unwrap(), document);
+    }
+
+    #[test]
+    fn test_changes_from() {
+        let original = StoryDocument::parse(
+            "---\ntype: bug\nestimate: 2\nlabels: a\n---\n\n# Fix it\n\nbroken",
+        )
+        .unwrap();
+
+        let unchanged = StoryDocument::parse(&original.render()).unwrap();
+        assert_eq!(
+            serde_json::to_value(unchanged.changes_from(&original)).unwrap(),
+            serde_json::json!({})
+        );
+
+        let edited = StoryDocument::parse(
+            "---\ntype: bug\nestimate:\nlabels: a, b\n---\n\n# Fix it now\n\nbroken",
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(edited.changes_from(&original)).unwrap(),
+            serde_json::json!({
+                "name": "Fix it now",
+                "estimate": null,
+                "labels": [{ "name": "a" }, { "name": "b" }],
+            })
+        );
+    }
+}
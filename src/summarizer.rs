@@ -0,0 +1,105 @@
+use anyhow::anyhow;
+use async_openai::types::{
+    ChatCompletionRequestMessageArgs, CreateChatCompletionRequestArgs, Role,
+};
+
+pub const DEFAULT_AI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// What to ask for: how to write, what to write about, and about how long the answer can be
+pub struct Request<'a> {
+    pub instructions: &'a str,
+    pub prompt: String,
+    pub max_tokens: u16,
+}
+
+pub enum Backend {
+    /// openai's chat api, or anything compatible with it, e.g. a llama.cpp or ollama server
+    OpenAi(async_openai::Client),
+    /// Answers with the model and the prompt it was given, so tests can see what was asked
+    Fake,
+}
+
+/// Writes commit subjects, standup notes and the like with a language model
+pub struct Summarizer {
+    backend: Backend,
+    model: String,
+    max_tokens: Option<u16>,
+}
+
+impl Summarizer {
+    pub fn new(backend: Backend, model: impl Into<String>) -> Self {
+        Summarizer {
+            backend,
+            model: model.into(),
+            max_tokens: None,
+        }
+    }
+
+    /// A client for an openai compatible api at `base_url`. Servers running on your own
+    /// machine usually don't need an `api_key`.
+    pub fn open_ai(base_url: &str, api_key: Option<String>, model: impl Into<String>) -> Self {
+        let client = async_openai::Client::new()
+            .with_api_base(base_url.trim_end_matches('/'))
+            .with_api_key(api_key.unwrap_or_default());
+        Summarizer::new(Backend::OpenAi(client), model)
+    }
+
+    /// Replaces every request's `max_tokens`, if there is one
+    pub fn max_tokens(mut self, max_tokens: Option<u16>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub async fn summarize(&self, request: Request<'_>) -> anyhow::Result<String> {
+        let max_tokens = self.max_tokens.unwrap_or(request.max_tokens);
+
+        match &self.backend {
+            Backend::OpenAi(client) => {
+                let chat = CreateChatCompletionRequestArgs::default()
+                    .model(&self.model)
+                    .messages([
+                        ChatCompletionRequestMessageArgs::default()
+                            .role(Role::System)
+                            .content(request.instructions)
+                            .build()?,
+                        ChatCompletionRequestMessageArgs::default()
+                            .role(Role::User)
+                            .content(request.prompt)
+                            .build()?,
+                    ])
+                    .max_tokens(max_tokens)
+                    .build()?;
+
+                let response = client.chat().create(chat).await?;
+
+                let first_choice = response
+                    .choices
+                    .first()
+                    .ok_or_else(|| anyhow!("didn't get a choice back from {}", self.model))?;
+
+                Ok(first_choice.message.content.trim().to_string())
+            }
+            Backend::Fake => Ok(format!("[{}] {}", self.model, request.prompt.trim())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fake() {
+        let summarizer = Summarizer::new(Backend::Fake, "gpt-test");
+        let summary = summarizer
+            .summarize(Request {
+                instructions: "be brief",
+                prompt: "Add a widget to the dashboard\n".to_string(),
+                max_tokens: 40,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(summary, "[gpt-test] Add a widget to the dashboard");
+    }
+}
@@ -0,0 +1,50 @@
+/// Fills in `{name}` placeholders with `values`. Anything in braces that isn't one of the
+/// values is left alone, so braces in code blocks and the like come through untouched.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            values
+                .iter()
+                .find(|(placeholder, _)| *placeholder == name)
+                .map(|(_, value)| (*value, end))
+        });
+
+        match value {
+            Some((value, end)) => {
+                out.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let values = [("name", "Add a widget"), ("id", "555")];
+
+        assert_eq!(render("{name} (#{id})", &values), "Add a widget (#555)");
+        assert_eq!(
+            render("fn main() { {name} } {unknown}", &values),
+            "fn main() { Add a widget } {unknown}"
+        );
+        assert_eq!(render("{{id}} {", &values), "{555} {");
+    }
+}
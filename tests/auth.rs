@@ -0,0 +1,183 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use common::*;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn test_login() {
+    let env = TestEnv::new().await;
+    fs::remove_file(env.token_file()).unwrap();
+
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .and(header("X-TrackerToken", "new-token"))
+        .respond_with(json_response(200, &fixture("me.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(json_response(
+            403,
+            r#"{"code":"invalid_authentication","kind":"error","error":"Invalid authentication credentials were presented."}"#,
+        ))
+        .mount(&env.server)
+        .await;
+
+    let rejected = env.stories_with_stdin(&["auth", "login"], "bad-token\n");
+    assert!(!rejected.status.success());
+    assert!(stderr(&rejected).contains("tracker didn't accept that token"));
+    assert!(!env.token_file().exists());
+
+    let output = env.stories_with_stdin(&["auth", "login"], "new-token\n");
+    assert_success(&output);
+    assert!(stdout(&output).contains("logged in to tracker as"));
+
+    assert_eq!(fs::read_to_string(env.token_file()).unwrap(), "new-token\n");
+    let mode = fs::metadata(env.token_file()).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[tokio::test]
+async fn test_logout_forgets_me() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+
+    assert_success(&env.stories(&["whoami"]));
+    assert!(stdout(&env.stories(&["cache", "ls"])).contains("/me"));
+
+    let output = env.stories(&["auth", "logout"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("logged out of tracker"));
+    assert!(!env.token_file().exists());
+    assert_eq!(
+        stdout(&env.stories(&["cache", "ls"])),
+        "the cache is empty\n"
+    );
+
+    let whoami = env.stories(&["whoami"]);
+    assert!(!whoami.status.success());
+    assert!(stderr(&whoami).contains("stories auth login"));
+}
+
+#[tokio::test]
+async fn test_world_readable_token_is_refused() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    fs::set_permissions(env.token_file(), fs::Permissions::from_mode(0o644)).unwrap();
+
+    let output = env.stories(&["whoami"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("chmod 600"));
+}
+
+#[tokio::test]
+async fn test_token_from_env() {
+    let env = TestEnv::new().await;
+    fs::remove_file(env.token_file()).unwrap();
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .and(header("X-TrackerToken", "env-token"))
+        .respond_with(json_response(200, &fixture("me.json")))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(403))
+        .mount(&env.server)
+        .await;
+
+    let vars = [("STORIES_TRACKER_TOKEN", "env-token")];
+    let status = env.stories_with_env(&["auth", "status"], &vars);
+    assert_success(&status);
+    let out = stdout(&status);
+    assert!(out.contains("tracker: logged in as"), "{}", out);
+    assert!(out.contains("token from $STORIES_TRACKER_TOKEN"), "{}", out);
+    assert!(out.contains("openai: not logged in"), "{}", out);
+
+    let logout = env.stories_with_env(&["auth", "logout"], &vars);
+    assert!(!logout.status.success());
+    assert!(stderr(&logout).contains("unset it to log out"));
+}
+
+#[tokio::test]
+async fn test_project_credential_helper_is_not_run() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+
+    let ran = env.project_dir().join("helper-ran");
+    fs::write(
+        env.project_dir().join("stories.json"),
+        serde_json::json!({
+            "project_id": PROJECT_ID,
+            "credential_helper": format!("touch {}; echo password=stolen", ran.display()),
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let output = env.stories(&["whoami"]);
+    assert_success(&output);
+    assert!(
+        stderr(&output).contains("ignoring the credential_helper in"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!ran.exists());
+}
+
+#[tokio::test]
+async fn test_project_base_url_gets_no_token() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(json_response(200, &fixture("me.json")))
+        .mount(&env.server)
+        .await;
+
+    fs::write(
+        env.project_dir().join("stories.json"),
+        serde_json::json!({"project_id": PROJECT_ID, "base_url": env.server.uri()}).to_string(),
+    )
+    .unwrap();
+
+    let output = env.stories_without_env(&["whoami"], &["STORIES_BASE_URL"]);
+    assert!(
+        stderr(&output).contains("not sending your tracker token to"),
+        "{}",
+        stderr(&output)
+    );
+
+    let requests = env.server.received_requests().await.unwrap();
+    assert!(!requests.is_empty());
+    for request in requests {
+        let headers = format!("{:?}", request.headers);
+        assert!(!headers.contains(TOKEN), "{}", headers);
+    }
+}
+
+#[tokio::test]
+async fn test_credential_helper_runs_once() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories", "stories.json").await;
+    fs::remove_file(env.token_file()).unwrap();
+
+    let runs = env.home.path().join("helper-runs");
+    let helper = format!(
+        "echo run >> {}; echo password={}; true",
+        runs.display(),
+        TOKEN
+    );
+    let set_in_project = env.stories(&["config", "set", "credential_helper", &helper]);
+    assert!(!set_in_project.status.success());
+    assert!(stderr(&set_in_project).contains("save it with --user"));
+
+    assert_success(&env.stories(&["config", "set", "--user", "credential_helper", &helper]));
+
+    assert_success(&env.stories(&["mine"]));
+    assert_eq!(fs::read_to_string(&runs).unwrap(), "run\n");
+}
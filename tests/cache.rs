@@ -0,0 +1,118 @@
+mod common;
+
+use common::*;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn test_me_is_cached() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/me"))
+        .respond_with(json_response(200, &fixture("me.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    assert_success(&env.stories(&["whoami"]));
+    assert_success(&env.stories(&["whoami"]));
+}
+
+#[tokio::test]
+async fn test_story_is_revalidated_with_etag() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories/555"))
+        .and(header("If-None-Match", "\"v1\""))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1)
+        .with_priority(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories/555"))
+        .respond_with(json_response(200, &fixture("story.json")).insert_header("ETag", "\"v1\""))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let first = env.stories(&["view", "555", "--json"]);
+    assert_success(&first);
+
+    let second = env.stories(&["view", "555", "--json"]);
+    assert_success(&second);
+    assert_eq!(stdout(&first), stdout(&second));
+}
+
+#[tokio::test]
+async fn test_offline() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    assert_success(&env.stories(&["view", "555", "--json"]));
+
+    env.server.reset().await;
+
+    let offline = env.stories(&["view", "555", "--json", "--offline"]);
+    assert_success(&offline);
+    assert_eq!(stdout(&offline).trim(), fixture("story.json").trim());
+
+    // the tasks were never fetched, so there just aren't any
+    let text = env.stories(&["view", "555", "--offline"]);
+    assert_success(&text);
+    assert!(stdout(&text).contains("Add a widget to the dashboard"));
+    assert!(!stdout(&text).contains("## Tasks"));
+
+    let uncached = env.stories(&["view", "556", "--json", "--offline"]);
+    assert!(!uncached.status.success());
+    assert!(stderr(&uncached).contains("can't be fetched while offline"));
+
+    assert_eq!(env.server.received_requests().await.unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_offline_tasks_and_comments() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    env.mock_get("/projects/1234/stories/555/tasks", "tasks.json")
+        .await;
+    env.mock_get("/projects/1234/stories/555/comments", "comments.json")
+        .await;
+
+    assert_success(&env.stories(&["view", "555", "--comments"]));
+
+    env.server.reset().await;
+
+    let offline = env.stories(&["view", "555", "--comments", "--offline"]);
+    assert_success(&offline);
+    let out = stdout(&offline);
+    assert!(out.contains("put it on the dashboard"), "{}", out);
+    assert!(out.contains("should the widget be"), "{}", out);
+}
+
+#[tokio::test]
+async fn test_cache_commands() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    assert_success(&env.stories(&["whoami"]));
+    assert_success(&env.stories(&["view", "555", "--json"]));
+
+    let ls = env.stories(&["cache", "ls"]);
+    assert_success(&ls);
+    assert!(stdout(&ls).contains("/projects/1234/stories/555"));
+    assert!(stdout(&ls).contains("/me"));
+
+    let stats = env.stories(&["cache", "stats"]);
+    assert_success(&stats);
+    assert!(stdout(&stats).contains("2 entries"), "{}", stdout(&stats));
+
+    assert_success(&env.stories(&["cache", "clear"]));
+
+    let offline = env.stories(&["whoami", "--offline"]);
+    assert!(!offline.status.success());
+}
@@ -0,0 +1,1223 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use std::fs;
+use wiremock::matchers::{body_json, method, path, query_param};
+use wiremock::Mock;
+
+#[tokio::test]
+async fn test_view() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    env.mock_get("/projects/1234/stories/555/tasks", "tasks.json")
+        .await;
+
+    let output = env.stories(&["view", "555"]);
+
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Add a widget to the dashboard"), "{}", out);
+    assert!(out.contains("put it on the dashboard"), "{}", out);
+    assert!(out.contains("Unstarted"), "{}", out);
+    assert!(out.contains("https://example.com/widgets"), "{}", out);
+}
+
+#[tokio::test]
+async fn test_view_json() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    let output = env.stories(&["view", "#555", "--json"]);
+
+    assert_success(&output);
+    assert_eq!(stdout(&output).trim(), fixture("story.json").trim());
+}
+
+#[tokio::test]
+async fn test_view_not_found() {
+    let env = TestEnv::new().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories/404"))
+        .respond_with(json_response(
+            404,
+            r#"{"code":"unfound_resource","kind":"error","error":"The object you tried to access could not be found."}"#,
+        ))
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["view", "404"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("unfound_resource"));
+}
+
+#[tokio::test]
+async fn test_branch() {
+    let env = TestEnv::new().await;
+    env.git(&["init", "--quiet"]);
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .and(body_json(json!({
+            "current_state": "started",
+            "owner_ids": [101],
+        })))
+        .respond_with(json_response(200, &fixture("started_story.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["branch", "555"]);
+
+    assert_success(&output);
+    let head = stdout(&env.git(&["symbolic-ref", "--short", "HEAD"]));
+    assert_eq!(head.trim(), "add-a-widget-to-the-dashboard-555");
+}
+
+#[tokio::test]
+async fn test_branch_already_started() {
+    let env = TestEnv::new().await;
+    env.git(&["init", "--quiet", "-b", "main"]);
+    env.git(&["commit", "--quiet", "--allow-empty", "-m", "initial"]);
+    env.git(&["branch", "widgets-from-before-555"]);
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/555", "started_story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .respond_with(json_response(200, &fixture("started_story.json")))
+        .expect(0)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["branch", "555"]);
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("Already started: #555"));
+    let head = stdout(&env.git(&["symbolic-ref", "--short", "HEAD"]));
+    assert_eq!(head.trim(), "widgets-from-before-555");
+
+    // and again, from the branch itself
+    assert_success(&env.stories(&["branch", "555"]));
+}
+
+#[tokio::test]
+async fn test_branch_leaves_tracker_alone_when_git_fails() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .respond_with(json_response(200, &fixture("started_story.json")))
+        .expect(0)
+        .mount(&env.server)
+        .await;
+
+    // not a git repository
+    let output = env.stories(&["branch", "555"]);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("not a git repository"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn test_branch_template() {
+    let env = TestEnv::new().await;
+    env.git(&["init", "--quiet"]);
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .respond_with(json_response(200, &fixture("started_story.json")))
+        .mount(&env.server)
+        .await;
+
+    fs::write(
+        env.project_dir().join("stories.json"),
+        r#"{"project_id": 1234, "branch_template": "{conventional_type}/PT-{id}-{slug}", "branch_slug_length": 18}"#,
+    )
+    .unwrap();
+
+    let output = env.stories(&["branch", "555", "--name", "widgets 2 electric boogaloo"]);
+    assert_success(&output);
+    let head = stdout(&env.git(&["symbolic-ref", "--short", "HEAD"]));
+    assert_eq!(head.trim(), "feat/PT-555-widgets-2-electric");
+
+    // the story is found from the branch, despite the number in the slug
+    let view = env.stories(&["view", "--json"]);
+    assert_success(&view);
+    assert!(stdout(&view).contains("Add a widget to the dashboard"));
+}
+
+#[tokio::test]
+async fn test_branch_with_estimate() {
+    let env = TestEnv::new().await;
+    env.git(&["init", "--quiet"]);
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .and(body_json(json!({
+            "current_state": "started",
+            "owner_ids": [101],
+            "estimate": 3,
+        })))
+        .respond_with(json_response(200, &fixture("started_story.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["branch", "555", "--estimate", "3"]);
+
+    assert_success(&output);
+}
+
+#[tokio::test]
+async fn test_mine() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories"))
+        .and(query_param("filter", "mywork:101"))
+        .respond_with(json_response(200, &fixture("stories.json")))
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["mine"]);
+
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Add a widget to the dashboard"), "{}", out);
+    assert!(out.contains("Widgets crash on tuesdays"), "{}", out);
+}
+
+#[tokio::test]
+async fn test_search() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+
+    let page = |offset: &str, returned: &str, body: String| {
+        json_response(200, &body)
+            .insert_header("X-Tracker-Pagination-Total", "3")
+            .insert_header("X-Tracker-Pagination-Limit", "100")
+            .insert_header("X-Tracker-Pagination-Offset", offset)
+            .insert_header("X-Tracker-Pagination-Returned", returned)
+    };
+    let third_story = r#"[{"kind":"story","id":558,"project_id":1234,"name":"Widgets on the moon","story_type":"feature","current_state":"started","url":"https://www.pivotaltracker.com/story/show/558","labels":[]}]"#;
+
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories"))
+        .and(query_param("filter", "widget state:started owner:dana"))
+        .and(query_param("offset", "0"))
+        .respond_with(page("0", "2", fixture("stories.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories"))
+        .and(query_param("filter", "widget state:started owner:dana"))
+        .and(query_param("offset", "2"))
+        .respond_with(page("2", "1", third_story.to_string()))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["search", "widget", "--state", "started", "--owner", "me"]);
+
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Add a widget to the dashboard"), "{}", out);
+    assert!(out.contains("Widgets crash on tuesdays"), "{}", out);
+    assert!(out.contains("Widgets on the moon"), "{}", out);
+
+    let nothing = env.stories(&["search"]);
+    assert!(!nothing.status.success());
+    assert!(stderr(&nothing).contains("give something to search for"));
+}
+
+#[tokio::test]
+async fn test_activity() {
+    let env = TestEnv::new().await;
+    env.mock_get("/my/activity", "activity.json").await;
+
+    let output = env.stories(&["activity"]);
+
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Add a widget to the dashboard"), "{}", out);
+    assert!(out.contains("started, finished"), "{}", out);
+    assert!(!out.contains("Something in another project"), "{}", out);
+}
+
+#[tokio::test]
+async fn test_activity_formats() {
+    let env = TestEnv::new().await;
+    env.mock_get("/my/activity", "activity.json").await;
+
+    let markdown = env.stories_with_env(&["activity", "--format", "markdown"], &[("TZ", "UTC")]);
+    assert_success(&markdown);
+    let out = stdout(&markdown);
+    assert!(out.contains("## Wed Jul 12"), "{}", out);
+    assert!(
+        out.contains("- [Add a widget to the dashboard](https://www.pivotaltracker.com/story/show/555): started → finished"),
+        "{}",
+        out
+    );
+
+    let json = env.stories_with_env(&["activity", "--format", "json"], &[("TZ", "UTC")]);
+    assert_success(&json);
+    let days: serde_json::Value = serde_json::from_str(&stdout(&json)).unwrap();
+    assert_eq!(days[0]["date"], "2023-07-12");
+    assert_eq!(days[0]["stories"][0]["id"], 555);
+    assert_eq!(days[0]["stories"][0]["transitions"][1]["state"], "finished");
+    assert!(!stdout(&json).contains("Something in another project"));
+}
+
+#[tokio::test]
+async fn test_activity_pages() {
+    let env = TestEnv::new().await;
+    let older = r#"[{"kind":"story_update_activity","guid":"1234_0","message":"Dana Developer delivered this bug","highlight":"delivered","primary_resources":[{"kind":"story","id":554,"name":"Widgets are too wide","story_type":"bug","url":"https://www.pivotaltracker.com/story/show/554"}],"project":{"kind":"project","id":1234,"name":"Widget Factory"},"occurred_at":"2023-07-10T09:00:00Z"}]"#;
+
+    let page = |offset: &str, returned: &str, body: &str| {
+        json_response(200, body)
+            .insert_header("X-Tracker-Pagination-Total", "4")
+            .insert_header("X-Tracker-Pagination-Limit", "100")
+            .insert_header("X-Tracker-Pagination-Offset", offset)
+            .insert_header("X-Tracker-Pagination-Returned", returned)
+    };
+    Mock::given(method("GET"))
+        .and(path("/my/activity"))
+        .and(query_param("offset", "0"))
+        .and(query_param("occurred_after", "2023-07-01T00:00:00+00:00"))
+        .respond_with(page("0", "3", &fixture("activity.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/my/activity"))
+        .and(query_param("offset", "3"))
+        .respond_with(page("3", "1", older))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories_with_env(
+        &["activity", "--all", "--since", "2023-07-01"],
+        &[("TZ", "UTC")],
+    );
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Add a widget to the dashboard"), "{}", out);
+    assert!(out.contains("Widgets are too wide"), "{}", out);
+
+    Mock::given(method("GET"))
+        .and(path("/my/activity"))
+        .and(query_param("limit", "2"))
+        .respond_with(page("0", "2", &fixture("activity.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    let limited = env.stories(&["activity", "--limit", "2", "--json"]);
+    assert_success(&limited);
+    let activities: Vec<serde_json::Value> = serde_json::from_str(&stdout(&limited)).unwrap();
+    assert_eq!(activities.len(), 2);
+}
+
+#[tokio::test]
+async fn test_team_activity() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/memberships", "memberships.json")
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/activity"))
+        .and(query_param("occurred_after", "2023-07-12T00:00:00+00:00"))
+        .and(query_param("occurred_before", "2023-07-14T00:00:00+00:00"))
+        .respond_with(json_response(200, &fixture("project_activity.json")))
+        .mount(&env.server)
+        .await;
+
+    let dates = ["--since", "2023-07-12", "--until", "2023-07-13"];
+    let everyone = env.stories_with_env(
+        &[&["activity", "--person", "all"][..], &dates].concat(),
+        &[("TZ", "UTC")],
+    );
+    assert_success(&everyone);
+    let out = stdout(&everyone);
+    assert!(out.contains("finished (DD)"), "{}", out);
+    assert!(out.contains("accepted (AB)"), "{}", out);
+
+    let alex = env.stories_with_env(
+        &[&["activity", "--person", "ab"][..], &dates].concat(),
+        &[("TZ", "UTC")],
+    );
+    assert_success(&alex);
+    let out = stdout(&alex);
+    assert!(out.contains("Widgets crash on tuesdays"), "{}", out);
+    assert!(!out.contains("Add a widget to the dashboard"), "{}", out);
+
+    let nobody = env.stories(&["activity", "--person", "zz"]);
+    assert!(!nobody.status.success());
+    assert!(stderr(&nobody).contains("nobody in the project goes by"));
+
+    let backwards = env.stories(&["activity", "--since", "2023-07-12", "--until", "2023-07-01"]);
+    assert!(!backwards.status.success());
+
+    let too_far = env.stories(&["activity", "--days", "4000000000"]);
+    assert!(!too_far.status.success());
+    assert!(stderr(&too_far).contains("goes back further than dates go"));
+    assert!(!stderr(&too_far).contains("panicked"));
+}
+
+#[tokio::test]
+async fn test_person_activity_pages() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/memberships", "memberships.json")
+        .await;
+
+    // alex's accept fills the first page, dana's finish is on the next one
+    let feed: Vec<serde_json::Value> =
+        serde_json::from_str(&fixture("project_activity.json")).unwrap();
+    let page = |offset: &str, activity: &serde_json::Value| {
+        json_response(200, &serde_json::json!([activity]).to_string())
+            .insert_header("X-Tracker-Pagination-Total", "2")
+            .insert_header("X-Tracker-Pagination-Limit", "1")
+            .insert_header("X-Tracker-Pagination-Offset", offset)
+            .insert_header("X-Tracker-Pagination-Returned", "1")
+    };
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/activity"))
+        .and(query_param("offset", "0"))
+        .respond_with(page("0", &feed[0]))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/activity"))
+        .and(query_param("offset", "1"))
+        .respond_with(page("1", &feed[1]))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories_with_env(
+        &["activity", "--person", "dd", "--limit", "1"],
+        &[("TZ", "UTC")],
+    );
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Add a widget to the dashboard"), "{}", out);
+    assert!(!out.contains("Widgets crash on tuesdays"), "{}", out);
+}
+
+#[tokio::test]
+async fn test_whoami() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+
+    let output = env.stories(&["whoami"]);
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("Dana Developer"));
+}
+
+#[tokio::test]
+async fn test_project_json() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234", "project.json").await;
+
+    let output = env.stories(&["project", "--json"]);
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("Widget Factory"));
+}
+
+#[tokio::test]
+async fn test_project() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234", "project.json").await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/iterations"))
+        .and(query_param("scope", "current"))
+        .respond_with(json_response(200, &fixture("current_iteration.json")))
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["project"]);
+
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Widget Factory #1234"), "{}", out);
+    assert!(out.contains("Velocity: 8"), "{}", out);
+    assert!(out.contains("Iteration 42: "), "{}", out);
+
+    // the stories and points columns
+    let row = |label: &str| -> Vec<u32> {
+        out.lines()
+            .find(|line| line.contains(label))
+            .unwrap_or_else(|| panic!("no {} row in {}", label, out))
+            .split(|c: char| c == '│' || c.is_whitespace())
+            .filter_map(|cell| cell.parse().ok())
+            .collect()
+    };
+    assert_eq!(row("Started"), vec![2, 2]);
+    assert_eq!(row("Accepted"), vec![1, 3]);
+    assert_eq!(row("Feature"), vec![2, 5]);
+    assert_eq!(row("Bug"), vec![1, 0]);
+}
+
+#[tokio::test]
+async fn test_iteration() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/memberships", "memberships.json")
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/iterations"))
+        .and(query_param("scope", "current"))
+        .respond_with(json_response(200, &fixture("current_iteration.json")))
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["iteration"]);
+
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Iteration 42 · "), "{}", out);
+    assert!(
+        out.contains("████████████░░░░░░░░ 3/5 points accepted"),
+        "{}",
+        out
+    );
+    let row = out.lines().find(|line| line.contains("555")).unwrap();
+    assert!(row.contains("Add a widget to the dashboard"), "{}", out);
+    assert!(row.contains("DD, AB"), "{}", out);
+
+    let iteration_41 = fixture("current_iteration.json")
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .replace("\"number\": 42", "\"number\": 41");
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/iterations/41"))
+        .respond_with(json_response(200, &iteration_41))
+        .mount(&env.server)
+        .await;
+    let output = env.stories(&["iteration", "41"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("Iteration 41 · "));
+
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/iterations/99"))
+        .respond_with(json_response(
+            404,
+            r#"{"code":"unfound_resource","kind":"error","error":"The object you tried to access could not be found."}"#,
+        ))
+        .mount(&env.server)
+        .await;
+    let missing = env.stories(&["iteration", "99"]);
+    assert!(!missing.status.success());
+    assert!(stderr(&missing).contains("there's no iteration 99"));
+
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/iterations"))
+        .and(query_param("scope", "backlog"))
+        .respond_with(json_response(200, "[]"))
+        .mount(&env.server)
+        .await;
+    let backlog = env.stories(&["iteration", "backlog"]);
+    assert_success(&backlog);
+    assert_eq!(stdout(&backlog), "the backlog is empty\n");
+
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories"))
+        .and(query_param("filter", "state:unscheduled"))
+        .respond_with(json_response(200, &fixture("stories.json")))
+        .mount(&env.server)
+        .await;
+    let output = env.stories(&["iteration", "icebox"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("2 stories, 2 points"));
+
+    let invalid = env.stories(&["iteration", "someday"]);
+    assert!(!invalid.status.success());
+    assert!(stderr(&invalid).contains("expected current, backlog, icebox or an iteration number"));
+}
+
+#[tokio::test]
+async fn test_icebox_json_pages() {
+    let env = TestEnv::new().await;
+    let stories: Vec<serde_json::Value> = serde_json::from_str(&fixture("stories.json")).unwrap();
+
+    let page = |offset: &str, body: &[serde_json::Value]| {
+        json_response(200, &serde_json::to_string(body).unwrap())
+            .insert_header("X-Tracker-Pagination-Total", "3")
+            .insert_header("X-Tracker-Pagination-Limit", "2")
+            .insert_header("X-Tracker-Pagination-Offset", offset)
+            .insert_header(
+                "X-Tracker-Pagination-Returned",
+                body.len().to_string().as_str(),
+            )
+    };
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories"))
+        .and(query_param("filter", "state:unscheduled"))
+        .and(query_param("offset", "0"))
+        .respond_with(page("0", &stories))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories"))
+        .and(query_param("filter", "state:unscheduled"))
+        .and(query_param("offset", "2"))
+        .respond_with(page("2", &stories[..1]))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["iteration", "icebox", "--json"]);
+    assert_success(&output);
+    let icebox: Vec<serde_json::Value> = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(icebox.len(), 3);
+}
+
+#[tokio::test]
+async fn test_pull_request() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    let title = env.stories(&["pr", "title", "555"]);
+    assert_success(&title);
+    assert_eq!(stdout(&title).trim(), "feat: Add a widget to the dashboard");
+
+    let body = env.stories(&["pr", "body", "555"]);
+    assert_success(&body);
+    assert!(stdout(&body).contains("Tracker: [delivers #555]"));
+}
+
+#[tokio::test]
+async fn test_pull_request_template() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    env.mock_get("/projects/1234/stories/555/tasks", "tasks.json")
+        .await;
+
+    fs::create_dir_all(env.project_dir().join(".github")).unwrap();
+    fs::write(
+        env.project_dir().join(".github/pr.md"),
+        "## {name} ({type}, {estimate} points)\n\n{description}\n\n{tasks}\n\nlabels: {labels}\n\n{trailer}\n",
+    )
+    .unwrap();
+    fs::write(
+        env.project_dir().join("stories.json"),
+        r#"{"project_id": 1234, "pr_template": ".github/pr.md"}"#,
+    )
+    .unwrap();
+
+    let nested = env.project_dir().join("src");
+    fs::create_dir_all(&nested).unwrap();
+    let body = env.stories_in(&nested, &["pr", "body", "555"]);
+    assert_success(&body);
+    let out = stdout(&body);
+    assert!(
+        out.starts_with(
+            "## Add a widget to the dashboard (feature, 2 points)\n\nThe dashboard needs a widget."
+        ),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("- [x] write the widget\n- [ ] put it on the dashboard"),
+        "{}",
+        out
+    );
+    assert!(out.contains("labels: dashboard"), "{}", out);
+    assert!(out.ends_with("[delivers #555]\n"), "{}", out);
+}
+
+#[tokio::test]
+async fn test_finish() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "started_story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .and(body_json(json!({ "current_state": "finished" })))
+        .respond_with(json_response(
+            200,
+            &fixture("started_story.json").replace("\"started\"", "\"finished\""),
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["finish", "555"]);
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("Finished"));
+}
+
+#[tokio::test]
+async fn test_transition_not_allowed() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .respond_with(json_response(500, "{}"))
+        .expect(0)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["deliver", "555"]);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("can't be delivered"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[tokio::test]
+async fn test_start_prompts_for_estimate() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/557", "unestimated_story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/557"))
+        .and(body_json(json!({
+            "current_state": "started",
+            "owner_ids": [101],
+        })))
+        .respond_with(json_response(
+            400,
+            r#"{"code":"invalid_parameter","kind":"error","error":"One or more request parameters was missing or invalid.","general_problem":"Stories in the started state must be estimated.","validation_errors":[{"field":"estimate","problem":"Stories in the started state must be estimated."}]}"#,
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/557"))
+        .and(body_json(json!({
+            "current_state": "started",
+            "owner_ids": [101],
+            "estimate": 3,
+        })))
+        .respond_with(json_response(
+            200,
+            &fixture("unestimated_story.json").replace("\"unstarted\"", "\"started\""),
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories_with_stdin(&["start", "557"], "3\n");
+
+    assert_success(&output);
+    assert!(stderr(&output).contains("must be estimated"));
+}
+
+#[tokio::test]
+async fn test_reject() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "delivered_story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .and(body_json(json!({ "current_state": "rejected" })))
+        .respond_with(json_response(
+            200,
+            &fixture("delivered_story.json").replace("\"delivered\"", "\"rejected\""),
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/projects/1234/stories/555/comments"))
+        .and(body_json(json!({ "text": "the widget is upside down" })))
+        .respond_with(json_response(
+            200,
+            r#"{"kind":"comment","id":1,"story_id":555,"text":"the widget is upside down","person_id":101,"created_at":"2023-07-13T12:00:00Z"}"#,
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["reject", "555", "--reason", "the widget is upside down"]);
+
+    assert_success(&output);
+}
+
+#[tokio::test]
+async fn test_reject_keeps_state_without_reason() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "delivered_story.json")
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/projects/1234/stories/555/comments"))
+        .respond_with(json_response(500, "{}"))
+        .mount(&env.server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .respond_with(json_response(200, &fixture("delivered_story.json")))
+        .expect(0)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["reject", "555", "--reason", "the widget is upside down"]);
+
+    assert!(!output.status.success());
+}
+
+#[tokio::test]
+async fn test_new() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/memberships", "memberships.json")
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/projects/1234/stories"))
+        .and(body_json(json!({
+            "name": "Widgets crash on tuesdays",
+            "story_type": "bug",
+            "description": "Only on tuesdays.",
+            "estimate": 1,
+            "labels": [{ "name": "dashboard" }],
+            "owner_ids": [101, 102],
+        })))
+        .respond_with(json_response(
+            200,
+            &fixture("story.json").replace("555", "558"),
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories_with_editor(&["new"], "new_story.md");
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("Created story: #558"));
+}
+
+#[tokio::test]
+async fn test_edit() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .and(body_json(json!({
+            "estimate": 3,
+            "description": "The dashboard needs a bigger widget.\n\nSee https://example.com/widgets",
+        })))
+        .respond_with(json_response(200, &fixture("story.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories_with_editor(&["edit", "555"], "edited_story.md");
+
+    assert_success(&output);
+}
+
+#[tokio::test]
+async fn test_edit_owners() {
+    let env = TestEnv::new().await;
+    env.mock_me().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    env.mock_get("/projects/1234/memberships", "memberships.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .and(body_json(json!({ "estimate": 3, "owner_ids": [102] })))
+        .respond_with(json_response(200, &fixture("story.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories_with_editor(&["edit", "555"], "reassigned_story.md");
+
+    assert_success(&output);
+}
+
+#[tokio::test]
+async fn test_edit_validation_error() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555"))
+        .and(body_json(json!({ "name": "x" })))
+        .respond_with(json_response(
+            400,
+            r#"{"code":"invalid_parameter","kind":"error","error":"One or more request parameters was missing or invalid.","validation_errors":[{"field":"name","problem":"Name is too short."}]}"#,
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories_with_editor(&["edit", "555"], "unnamed_story.md");
+
+    assert!(!output.status.success());
+    let err = stderr(&output);
+    assert!(err.contains("name: Name is too short."), "{}", err);
+    assert!(err.contains("your draft was saved"), "{}", err);
+
+    let drafts: Vec<_> = fs::read_dir(env.home.path().join(".config/stories/drafts"))
+        .unwrap()
+        .collect();
+    assert_eq!(drafts.len(), 1);
+    let draft = drafts[0].as_ref().unwrap().path();
+    assert!(err.contains(&draft.display().to_string()), "{}", err);
+    assert_eq!(
+        fs::read_to_string(draft).unwrap(),
+        fixture("unnamed_story.md")
+    );
+}
+
+#[tokio::test]
+async fn test_view_comments() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    env.mock_get("/projects/1234/stories/555/tasks", "tasks.json")
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/1234/stories/555/comments"))
+        .and(query_param("fields", ":default,person"))
+        .respond_with(json_response(200, &fixture("comments.json")))
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["view", "555", "--comments"]);
+
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(out.contains("Alex Builder"), "{}", out);
+    assert!(out.contains("should the widget be"), "{}", out);
+}
+
+#[tokio::test]
+async fn test_comment() {
+    let env = TestEnv::new().await;
+    Mock::given(method("POST"))
+        .and(path("/projects/1234/stories/555/comments"))
+        .and(body_json(json!({ "text": "looks good" })))
+        .respond_with(json_response(200, &fixture("comment.json")))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    assert_success(&env.stories(&["comment", "555", "--message", "looks good"]));
+    assert_success(&env.stories_with_stdin(&["comment", "555"], "looks good\n"));
+}
+
+#[tokio::test]
+async fn test_comment_reply() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555/comments/9001", "comment.json")
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/projects/1234/stories/555/comments"))
+        .and(body_json(json!({
+            "text": "@alex wrote:\n> should the widget be **blue**?\n\nno, green",
+        })))
+        .respond_with(json_response(200, &fixture("comment.json")))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    let output = env.stories(&["comment", "555", "--reply-to", "9001", "-m", "no, green"]);
+
+    assert_success(&output);
+}
+
+#[tokio::test]
+async fn test_task() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555/tasks", "tasks.json")
+        .await;
+
+    let list = env.stories(&["task", "--story", "555"]);
+    assert_success(&list);
+    assert_eq!(
+        stdout(&list),
+        "1. [x] write the widget\n2. [ ] put it on the dashboard\n"
+    );
+
+    Mock::given(method("POST"))
+        .and(path("/projects/1234/stories/555/tasks"))
+        .and(body_json(json!({ "description": "test the widget" })))
+        .respond_with(json_response(
+            200,
+            r#"{"kind":"task","id":33,"story_id":555,"description":"test the widget","complete":false,"position":3}"#,
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    let add = env.stories(&["task", "add", "test the widget", "--story", "555"]);
+    assert_success(&add);
+    assert_eq!(stdout(&add), "3. [ ] test the widget\n");
+
+    Mock::given(method("PUT"))
+        .and(path("/projects/1234/stories/555/tasks/32"))
+        .and(body_json(json!({ "complete": true })))
+        .respond_with(json_response(
+            200,
+            r#"{"kind":"task","id":32,"story_id":555,"description":"put it on the dashboard","complete":true,"position":2}"#,
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    assert_success(&env.stories(&["task", "done", "2", "--story", "555"]));
+
+    Mock::given(method("DELETE"))
+        .and(path("/projects/1234/stories/555/tasks/31"))
+        .respond_with(wiremock::ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    assert_success(&env.stories(&["task", "rm", "1", "--story", "555"]));
+
+    let missing = env.stories(&["task", "undo", "9", "--story", "555"]);
+    assert!(!missing.status.success());
+    assert!(stderr(&missing).contains("doesn't have a task 9"));
+}
+
+#[tokio::test]
+async fn test_view_from_git_branch() {
+    let env = TestEnv::new().await;
+    env.git(&["init", "--quiet"]);
+    env.git(&["switch", "--quiet", "-c", "add-a-widget-555"]);
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    env.mock_get("/projects/1234/stories/556", "story.json")
+        .await;
+
+    assert_success(&env.stories(&["view", "--json"]));
+    assert_success(&env.stories(&["view", "--json", "--branch", "someone-elses-556"]));
+
+    env.git(&["commit", "--quiet", "--allow-empty", "-m", "initial"]);
+    env.git(&["switch", "--quiet", "--detach"]);
+    assert_success(&env.stories(&["view", "--json"]));
+}
+
+#[tokio::test]
+async fn test_view_from_subdirectory() {
+    let env = TestEnv::new().await;
+    env.git(&["init", "--quiet"]);
+    env.git(&["switch", "--quiet", "-c", "add-a-widget-555"]);
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    let nested = env.project_dir().join("src/deeply/nested");
+    fs::create_dir_all(&nested).unwrap();
+
+    let output = env.stories_in(&nested, &["view", "--json"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("Add a widget"));
+}
+
+#[tokio::test]
+async fn test_config() {
+    let env = TestEnv::new().await;
+    let project_file = env.project_dir().join("stories.json");
+
+    assert_success(&env.stories(&["config", "set", "branch_prefix", "feat/"]));
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&project_file).unwrap()).unwrap();
+    assert_eq!(
+        saved,
+        json!({ "project_id": 1234, "branch_prefix": "feat/" })
+    );
+
+    let get = env.stories(&["config", "get", "branch_prefix"]);
+    assert_success(&get);
+    assert_eq!(stdout(&get), "feat/\n");
+
+    assert_success(&env.stories(&["config", "set", "--user", "ai_model", "gpt-4"]));
+    let list = env.stories(&["config", "list"]);
+    assert_success(&list);
+    assert!(stdout(&list).contains("gpt-4"));
+    assert!(stdout(&list).contains(".config/stories/config.json"));
+    assert!(stdout(&list).contains("$STORIES_BASE_URL"));
+
+    let invalid = env.stories(&["config", "set", "output_format", "yaml"]);
+    assert!(!invalid.status.success());
+    assert!(stderr(&invalid).contains("should be one of text, json"));
+
+    assert_success(&env.stories(&["config", "set", "output_format", "json"]));
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    let view = env.stories(&["view", "555"]);
+    assert_success(&view);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&stdout(&view)).unwrap(),
+        serde_json::from_str::<serde_json::Value>(&fixture("story.json")).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_multiple_projects() {
+    let env = TestEnv::new().await;
+    fs::write(
+        env.project_dir().join("stories.json"),
+        r#"{"projects": {"web": 1234, "api": 5678}}"#,
+    )
+    .unwrap();
+
+    // a bare story id is looked up to find its project, unless --project picks one
+    let api_story = fixture("story.json").replace("1234", "5678");
+    Mock::given(method("GET"))
+        .and(path("/stories/555"))
+        .respond_with(json_response(200, &api_story))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/projects/5678/stories/555"))
+        .respond_with(json_response(200, &api_story))
+        .expect(2)
+        .mount(&env.server)
+        .await;
+
+    assert_success(&env.stories(&["view", "555", "--json"]));
+    assert_success(&env.stories(&["view", "555", "--json", "--project", "api"]));
+
+    env.mock_me().await;
+    for project_id in [1234, 5678] {
+        Mock::given(method("GET"))
+            .and(path(format!("/projects/{}/stories", project_id)))
+            .and(query_param("filter", "mywork:101"))
+            .respond_with(json_response(200, &fixture("stories.json")))
+            .expect(1..)
+            .mount(&env.server)
+            .await;
+    }
+
+    let mine = env.stories(&["mine"]);
+    assert_success(&mine);
+    let out = stdout(&mine);
+    assert!(out.contains("web Add a widget to the dashboard"), "{}", out);
+    assert!(out.contains("api Add a widget to the dashboard"), "{}", out);
+
+    let mine_json = env.stories(&["mine", "--json"]);
+    assert_success(&mine_json);
+    let stories: Vec<serde_json::Value> = serde_json::from_str(&stdout(&mine_json)).unwrap();
+    assert_eq!(stories.len(), 4);
+
+    // --limit covers every project's feed together
+    for project_id in [1234, 5678] {
+        Mock::given(method("GET"))
+            .and(path(format!("/projects/{}/activity", project_id)))
+            .respond_with(json_response(200, &fixture("project_activity.json")))
+            .mount(&env.server)
+            .await;
+    }
+    let activity = env.stories(&["activity", "--person", "all", "--limit", "1", "--json"]);
+    assert_success(&activity);
+    let activities: Vec<serde_json::Value> = serde_json::from_str(&stdout(&activity)).unwrap();
+    assert_eq!(activities.len(), 1);
+
+    let new = env.stories(&["new"]);
+    assert!(!new.status.success());
+    assert!(
+        stderr(&new).contains("--project api|web"),
+        "{}",
+        stderr(&new)
+    );
+}
+
+#[tokio::test]
+async fn test_hooks() {
+    let env = TestEnv::new().await;
+    env.git(&["init", "--quiet", "-b", "main"]);
+
+    let installed = env.stories(&["hooks", "install", "--mode", "delivers"]);
+    assert_success(&installed);
+    assert!(stdout(&installed).contains("prepare-commit-msg"));
+
+    let message = |env: &TestEnv| stdout(&env.git(&["log", "-1", "--format=%B"]));
+
+    // nothing to add on branches without a story
+    env.git(&["commit", "--quiet", "--allow-empty", "-m", "initial"]);
+    assert_eq!(message(&env).trim(), "initial");
+
+    env.git(&["switch", "--quiet", "-c", "add-a-widget-555"]);
+    env.git(&["commit", "--quiet", "--allow-empty", "-m", "add a widget"]);
+    assert_eq!(message(&env).trim(), "add a widget\n\n[delivers #555]");
+
+    env.git(&["commit", "--quiet", "--allow-empty", "-m", "polish [#555]"]);
+    assert_eq!(message(&env).trim(), "polish [#555]");
+
+    // a message left empty in the editor is still aborted
+    let empty = std::process::Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(["commit", "--allow-empty"])
+        .env("GIT_EDITOR", "true")
+        .current_dir(env.project_dir())
+        .output()
+        .unwrap();
+    assert!(!empty.status.success());
+    assert!(
+        stderr(&empty).contains("empty commit message"),
+        "{}",
+        stderr(&empty)
+    );
+    assert_eq!(message(&env).trim(), "polish [#555]");
+
+    let message_file = env.project_dir().join("message.txt");
+
+    // merges, squashes and amends keep their message
+    fs::write(&message_file, "Merge branch 'main'\n").unwrap();
+    let merge = env.stories(&[
+        "hooks",
+        "run",
+        "prepare-commit-msg",
+        "--mode",
+        "id",
+        message_file.to_str().unwrap(),
+        "merge",
+    ]);
+    assert_success(&merge);
+    assert_eq!(
+        fs::read_to_string(&message_file).unwrap(),
+        "Merge branch 'main'\n"
+    );
+
+    fs::write(&message_file, "no story here\n").unwrap();
+    let rejected = env.stories(&[
+        "hooks",
+        "run",
+        "commit-msg",
+        "--mode",
+        "id",
+        message_file.to_str().unwrap(),
+    ]);
+    assert!(!rejected.status.success());
+    assert!(stderr(&rejected).contains("add [#555] to it or commit with --no-verify"));
+
+    let uninstalled = env.stories(&["hooks", "uninstall"]);
+    assert_success(&uninstalled);
+    env.git(&["commit", "--quiet", "--allow-empty", "-m", "plain"]);
+    assert_eq!(message(&env).trim(), "plain");
+}
@@ -0,0 +1,192 @@
+//! Shared setup for the end-to-end tests: a throwaway $HOME with an api token, a project
+//! directory with a stories.json, and a mock tracker api the binary is pointed at.
+
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use tempfile::TempDir;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+pub const PROJECT_ID: u64 = 1234;
+pub const TOKEN: &str = "test-token";
+
+pub struct TestEnv {
+    pub server: MockServer,
+    pub home: TempDir,
+    pub project: TempDir,
+}
+
+impl TestEnv {
+    pub async fn new() -> Self {
+        let server = MockServer::start().await;
+
+        let home = tempfile::tempdir().unwrap();
+        let config_dir = home.path().join(".config/stories");
+        fs::create_dir_all(&config_dir).unwrap();
+        let token_file = config_dir.join("tracker_api_token.txt");
+        fs::write(&token_file, format!("{}\n", TOKEN)).unwrap();
+        fs::set_permissions(&token_file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let project = tempfile::tempdir().unwrap();
+        fs::write(
+            project.path().join("stories.json"),
+            format!(r#"{{"project_id":{}}}"#, PROJECT_ID),
+        )
+        .unwrap();
+
+        TestEnv {
+            server,
+            home,
+            project,
+        }
+    }
+
+    pub fn project_dir(&self) -> PathBuf {
+        self.project.path().to_path_buf()
+    }
+
+    /// Runs the stories binary in the project directory against the mock server
+    pub fn stories(&self, args: &[&str]) -> Output {
+        self.stories_in(&self.project_dir(), args)
+    }
+
+    pub fn stories_in(&self, dir: &Path, args: &[&str]) -> Output {
+        self.command(dir, args).output().unwrap()
+    }
+
+    /// Runs the stories binary in the project directory with extra env vars
+    pub fn stories_with_env(&self, args: &[&str], vars: &[(&str, &str)]) -> Output {
+        self.command(&self.project_dir(), args)
+            .envs(vars.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    /// Runs the stories binary in the project directory with some of the usual env vars unset
+    pub fn stories_without_env(&self, args: &[&str], vars: &[&str]) -> Output {
+        let mut command = self.command(&self.project_dir(), args);
+        for var in vars {
+            command.env_remove(var);
+        }
+        command.output().unwrap()
+    }
+
+    /// Runs the stories binary in the project directory, answering prompts with `input`
+    pub fn stories_with_stdin(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self
+            .command(&self.project_dir(), args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// Runs the stories binary with an $EDITOR that replaces whatever it's given with `fixture_name`
+    pub fn stories_with_editor(&self, args: &[&str], fixture_name: &str) -> Output {
+        let fixture_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(fixture_name);
+        self.command(&self.project_dir(), args)
+            .env_remove("VISUAL")
+            .env("EDITOR", format!("cp {}", fixture_path.display()))
+            .output()
+            .unwrap()
+    }
+
+    fn command(&self, dir: &Path, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_stories"));
+        command
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", self.home.path())
+            .env("STORIES_BASE_URL", self.server.uri())
+            .env("NO_COLOR", "1")
+            .env_remove("STORIES_TRACKER_TOKEN")
+            .env_remove("OPENAI_API_KEY")
+            .stdin(Stdio::null());
+        command
+    }
+
+    pub fn token_file(&self) -> PathBuf {
+        self.home
+            .path()
+            .join(".config/stories/tracker_api_token.txt")
+    }
+
+    pub fn git(&self, args: &[&str]) -> Output {
+        git_in(&self.project_dir(), args)
+    }
+
+    /// Serves a fixture from tests/fixtures for GET requests to `route`
+    pub async fn mock_get(&self, route: &str, fixture_name: &str) {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .and(header("X-TrackerToken", TOKEN))
+            .respond_with(json_response(200, &fixture(fixture_name)))
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn mock_me(&self) {
+        self.mock_get("/me", "me.json").await;
+    }
+}
+
+pub fn git_in(dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+pub fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    fs::read_to_string(path).unwrap()
+}
+
+pub fn json_response(status: u16, body: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(body.to_string(), "application/json")
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[track_caller]
+pub fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "stories failed\n\nstdout:\n{}\n\nstderr:\n{}",
+        stdout(output),
+        stderr(output)
+    );
+}
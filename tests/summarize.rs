@@ -0,0 +1,193 @@
+mod common;
+
+use common::*;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::Mock;
+
+#[tokio::test]
+async fn test_fake_backend() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+    env.mock_get("/stories/555", "story.json").await;
+    env.mock_get("/my/activity", "activity.json").await;
+
+    let fake = [("STORIES_AI_BACKEND", "fake")];
+
+    let title = env.stories_with_env(&["pr", "title", "555", "--summarize"], &fake);
+    assert_success(&title);
+    let out = stdout(&title);
+    assert!(
+        out.starts_with(
+            "[gpt-3.5-turbo] Write a git commit subject for a change that implements this:"
+        ),
+        "{}",
+        out
+    );
+    assert!(out.contains("The dashboard needs a widget."), "{}", out);
+
+    let standup = env.stories_with_env(
+        &["activity", "--summarize", "--model", "llama3"],
+        &[("STORIES_AI_BACKEND", "fake"), ("TZ", "UTC")],
+    );
+    assert_success(&standup);
+    let out = stdout(&standup);
+    assert!(out.starts_with("[llama3] Wednesday 2023-07-12"), "{}", out);
+    assert!(
+        out.contains(
+            "- Add a widget to the dashboard: started, finished\n  The dashboard needs a widget."
+        ),
+        "{}",
+        out
+    );
+}
+
+#[tokio::test]
+async fn test_standup_without_descriptions() {
+    let env = TestEnv::new().await;
+    env.mock_get("/my/activity", "activity.json").await;
+    Mock::given(method("GET"))
+        .and(path("/stories/555"))
+        .respond_with(json_response(
+            404,
+            r#"{"code":"unfound_resource","kind":"error","error":"The object you tried to access could not be found."}"#,
+        ))
+        .mount(&env.server)
+        .await;
+
+    let standup = env.stories_with_env(
+        &["activity", "--summarize"],
+        &[("STORIES_AI_BACKEND", "fake"), ("TZ", "UTC")],
+    );
+    assert_success(&standup);
+    let out = stdout(&standup);
+    assert!(
+        out.contains("- Add a widget to the dashboard: started, finished\n"),
+        "{}",
+        out
+    );
+    assert!(!out.contains("The dashboard needs a widget."), "{}", out);
+}
+
+#[tokio::test]
+async fn test_openai_compatible_server() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(body_partial_json(
+            serde_json::json!({"model": "llama3", "max_tokens": 60}),
+        ))
+        .respond_with(json_response(
+            200,
+            r#"{"id":"chatcmpl-1","object":"chat.completion","created":1689000000,"model":"llama3","choices":[{"index":0,"message":{"role":"assistant","content":"feat: add a dashboard widget\n"},"finish_reason":"stop"}]}"#,
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    // a local server doesn't need an openai key
+    let base_url = format!("{}/v1", env.server.uri());
+    let output = env.stories_with_env(
+        &["pr", "title", "555", "--summarize"],
+        &[
+            ("STORIES_AI_BASE_URL", base_url.as_str()),
+            ("STORIES_AI_MODEL", "llama3"),
+            ("STORIES_AI_MAX_TOKENS", "60"),
+        ],
+    );
+    assert_success(&output);
+    assert_eq!(stdout(&output), "feat: add a dashboard widget\n");
+}
+
+#[tokio::test]
+async fn test_project_base_url_gets_no_key() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(json_response(
+            200,
+            r#"{"id":"chatcmpl-1","object":"chat.completion","created":1689000000,"model":"llama3","choices":[{"index":0,"message":{"role":"assistant","content":"feat: add a dashboard widget\n"},"finish_reason":"stop"}]}"#,
+        ))
+        .expect(1)
+        .mount(&env.server)
+        .await;
+
+    std::fs::write(
+        env.project_dir().join("stories.json"),
+        serde_json::json!({
+            "project_id": PROJECT_ID,
+            "ai_base_url": format!("{}/v1", env.server.uri()),
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let output = env.stories_with_env(
+        &["pr", "title", "555", "--summarize"],
+        &[("OPENAI_API_KEY", "sk-user")],
+    );
+    assert_success(&output);
+    assert!(stderr(&output).contains("not sending your openai key to"));
+
+    let requests = env.server.received_requests().await.unwrap();
+    let completion = requests
+        .iter()
+        .find(|request| request.url.path() == "/v1/chat/completions")
+        .unwrap();
+    let headers = format!("{:?}", completion.headers);
+    assert!(!headers.contains("sk-user"), "{}", headers);
+}
+
+#[tokio::test]
+async fn test_openai_needs_a_key() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    let output = env.stories(&["pr", "title", "555", "--summarize"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("not logged in to openai"));
+}
+
+#[tokio::test]
+async fn test_pull_request_body() {
+    let env = TestEnv::new().await;
+    env.mock_get("/projects/1234/stories/555", "story.json")
+        .await;
+
+    env.git(&["init", "--quiet", "-b", "main"]);
+    env.git(&["commit", "--quiet", "--allow-empty", "-m", "initial"]);
+    env.git(&["switch", "--quiet", "-c", "widgets"]);
+    std::fs::write(env.project_dir().join("widget.rs"), "fn widget() {}\n").unwrap();
+    env.git(&["add", "widget.rs"]);
+    env.git(&["commit", "--quiet", "-m", "add a widget"]);
+
+    let output = env.stories_with_env(
+        &["pr", "body", "555", "--summarize"],
+        &[("STORIES_AI_BACKEND", "fake")],
+    );
+    assert_success(&output);
+    let out = stdout(&output);
+    assert!(
+        out.starts_with("[gpt-3.5-turbo] Story: Add a widget to the dashboard (feature)"),
+        "{}",
+        out
+    );
+    assert!(out.contains("Commits:\n- add a widget\n"), "{}", out);
+    assert!(out.contains("Files changed compared to main:"), "{}", out);
+    assert!(out.contains("+fn widget() {}"), "{}", out);
+    assert!(out.ends_with("Tracker: [delivers #555]\n"), "{}", out);
+
+    let unknown_base = env.stories_with_env(
+        &["pr", "body", "555", "--summarize", "--base", "nope"],
+        &[("STORIES_AI_BACKEND", "fake")],
+    );
+    assert!(!unknown_base.status.success());
+    assert!(stderr(&unknown_base).contains("couldn't find where HEAD forked from nope"));
+}